@@ -81,8 +81,12 @@ class GbEmulator {
     _windowScaleFactor = (screenWidth / gbWindowWidth).floor();
     _windowWidth = gbWindowWidth.toDouble() * _windowScaleFactor;
     _windowHeight = gbWindowHeight.toDouble() * _windowScaleFactor;
-    _windowConfig = malloc<WindowConfig>();
+    _windowConfig = calloc<WindowConfig>();
     _windowConfig.ref.scale_factor = _windowScaleFactor.toDouble();
+    _windowConfig.ref.width = _windowWidth.toInt();
+    _windowConfig.ref.height = _windowHeight.toInt();
+    _windowConfig.ref.scale_mode = ScaleMode.INTEGER;
+    _windowConfig.ref.filter = FilterKind.FILTER_NONE;
     // Create emulator
     _emulator = FFIBinding.binding.create_emulator(_windowConfig);
   }
@@ -107,7 +111,7 @@ class GbEmulator {
   void exit() {
     FFIBinding.binding.exit_emulator(_emulator);
     _buffer?.dispose();
-    malloc.free(_windowConfig);
+    calloc.free(_windowConfig);
   }
 
   void pressButton(GbButton button) {
@@ -54,6 +54,99 @@ class NativeBinding {
       void Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
           ffi.Pointer<ffi.Char>)>();
 
+  void run_emulator_from_bytes(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Uint8> data,
+    int len,
+    ffi.Pointer<ffi.Char> save_path,
+  ) {
+    return _run_emulator_from_bytes(
+      emulator,
+      data,
+      len,
+      save_path,
+    );
+  }
+
+  late final _run_emulator_from_bytesPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Uint8>,
+              ffi.Size, ffi.Pointer<ffi.Char>)>>('run_emulator_from_bytes');
+  late final _run_emulator_from_bytes = _run_emulator_from_bytesPtr.asFunction<
+      void Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Uint8>, int,
+          ffi.Pointer<ffi.Char>)>();
+
+  void set_lenient_loading(
+    ffi.Pointer<Emulator_C> emulator,
+    bool lenient,
+  ) {
+    return _set_lenient_loading(
+      emulator,
+      lenient,
+    );
+  }
+
+  late final _set_lenient_loadingPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Bool)>>('set_lenient_loading');
+  late final _set_lenient_loading = _set_lenient_loadingPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, bool)>();
+
+  void set_patch_path(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> patch_path,
+  ) {
+    return _set_patch_path(
+      emulator,
+      patch_path,
+    );
+  }
+
+  late final _set_patch_pathPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(ffi.Pointer<Emulator_C>,
+              ffi.Pointer<ffi.Char>)>>('set_patch_path');
+  late final _set_patch_path = _set_patch_pathPtr.asFunction<
+      void Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>();
+
+  bool read_rom_info(
+    ffi.Pointer<ffi.Char> rom_path,
+    ffi.Pointer<RomInfo> info,
+  ) {
+    return _read_rom_info(
+      rom_path,
+      info,
+    );
+  }
+
+  late final _read_rom_infoPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(
+              ffi.Pointer<ffi.Char>, ffi.Pointer<RomInfo>)>>('read_rom_info');
+  late final _read_rom_info = _read_rom_infoPtr
+      .asFunction<bool Function(ffi.Pointer<ffi.Char>, ffi.Pointer<RomInfo>)>();
+
+  bool read_rom_info_from_bytes(
+    ffi.Pointer<ffi.Uint8> data,
+    int len,
+    ffi.Pointer<RomInfo> info,
+  ) {
+    return _read_rom_info_from_bytes(
+      data,
+      len,
+      info,
+    );
+  }
+
+  late final _read_rom_info_from_bytesPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<ffi.Uint8>, ffi.Size,
+              ffi.Pointer<RomInfo>)>>('read_rom_info_from_bytes');
+  late final _read_rom_info_from_bytes =
+      _read_rom_info_from_bytesPtr.asFunction<
+          bool Function(ffi.Pointer<ffi.Uint8>, int, ffi.Pointer<RomInfo>)>();
+
   ffi.Pointer<ffi.Uint32> get_window_buffer(
     ffi.Pointer<Emulator_C> emulator,
   ) {
@@ -69,6 +162,119 @@ class NativeBinding {
   late final _get_window_buffer = _get_window_bufferPtr
       .asFunction<ffi.Pointer<ffi.Uint32> Function(ffi.Pointer<Emulator_C>)>();
 
+  ffi.Pointer<ffi.Uint32> get_window_frame(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Uint64> seq,
+  ) {
+    return _get_window_frame(
+      emulator,
+      seq,
+    );
+  }
+
+  late final _get_window_framePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Pointer<ffi.Uint32> Function(ffi.Pointer<Emulator_C>,
+              ffi.Pointer<ffi.Uint64>)>>('get_window_frame');
+  late final _get_window_frame = _get_window_framePtr.asFunction<
+      ffi.Pointer<ffi.Uint32> Function(
+          ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Uint64>)>();
+
+  bool copy_native_frame(
+    ffi.Pointer<Emulator_C> emulator,
+    int format,
+    ffi.Pointer<ffi.Uint8> buffer,
+    int len,
+  ) {
+    return _copy_native_frame(
+      emulator,
+      format,
+      buffer,
+      len,
+    );
+  }
+
+  late final _copy_native_framePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<Emulator_C>, ffi.Int32,
+              ffi.Pointer<ffi.Uint8>, ffi.Size)>>('copy_native_frame');
+  late final _copy_native_frame = _copy_native_framePtr.asFunction<
+      bool Function(
+          ffi.Pointer<Emulator_C>, int, ffi.Pointer<ffi.Uint8>, int)>();
+
+  bool save_screenshot(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> path,
+    bool scaled,
+    bool metadata,
+  ) {
+    return _save_screenshot(
+      emulator,
+      path,
+      scaled,
+      metadata,
+    );
+  }
+
+  late final _save_screenshotPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
+              ffi.Bool, ffi.Bool)>>('save_screenshot');
+  late final _save_screenshot = _save_screenshotPtr.asFunction<
+      bool Function(
+          ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>, bool, bool)>();
+
+  void set_ghosting(
+    ffi.Pointer<Emulator_C> emulator,
+    double persistence,
+  ) {
+    return _set_ghosting(
+      emulator,
+      persistence,
+    );
+  }
+
+  late final _set_ghostingPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Float)>>('set_ghosting');
+  late final _set_ghosting = _set_ghostingPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, double)>();
+
+  void set_pixel_grid(
+    ffi.Pointer<Emulator_C> emulator,
+    bool enabled,
+  ) {
+    return _set_pixel_grid(
+      emulator,
+      enabled,
+    );
+  }
+
+  late final _set_pixel_gridPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Bool)>>('set_pixel_grid');
+  late final _set_pixel_grid = _set_pixel_gridPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, bool)>();
+
+  void set_dmg_tint(
+    ffi.Pointer<Emulator_C> emulator,
+    bool enabled,
+  ) {
+    return _set_dmg_tint(
+      emulator,
+      enabled,
+    );
+  }
+
+  late final _set_dmg_tintPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Bool)>>('set_dmg_tint');
+  late final _set_dmg_tint = _set_dmg_tintPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, bool)>();
+
   void press_button(
     ffi.Pointer<Emulator_C> emulator,
     int btn,
@@ -103,6 +309,155 @@ class NativeBinding {
   late final _release_button = _release_buttonPtr
       .asFunction<void Function(ffi.Pointer<Emulator_C>, int)>();
 
+  void key_down(
+    ffi.Pointer<Emulator_C> emulator,
+    int source,
+    int code,
+  ) {
+    return _key_down(
+      emulator,
+      source,
+      code,
+    );
+  }
+
+  late final _key_downPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Int32, ffi.Uint32)>>('key_down');
+  late final _key_down = _key_downPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, int, int)>();
+
+  void key_up(
+    ffi.Pointer<Emulator_C> emulator,
+    int source,
+    int code,
+  ) {
+    return _key_up(
+      emulator,
+      source,
+      code,
+    );
+  }
+
+  late final _key_upPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Int32, ffi.Uint32)>>('key_up');
+  late final _key_up =
+      _key_upPtr.asFunction<void Function(ffi.Pointer<Emulator_C>, int, int)>();
+
+  void map_key(
+    ffi.Pointer<Emulator_C> emulator,
+    int source,
+    int code,
+    int buttons,
+  ) {
+    return _map_key(
+      emulator,
+      source,
+      code,
+      buttons,
+    );
+  }
+
+  late final _map_keyPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(ffi.Pointer<Emulator_C>, ffi.Int32, ffi.Uint32,
+              ffi.Uint8)>>('map_key');
+  late final _map_key = _map_keyPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, int, int, int)>();
+
+  void set_opposite_policy(
+    ffi.Pointer<Emulator_C> emulator,
+    int policy,
+  ) {
+    return _set_opposite_policy(
+      emulator,
+      policy,
+    );
+  }
+
+  late final _set_opposite_policyPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Int32)>>('set_opposite_policy');
+  late final _set_opposite_policy = _set_opposite_policyPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, int)>();
+
+  void set_turbo(
+    ffi.Pointer<Emulator_C> emulator,
+    int btn,
+    int frames,
+  ) {
+    return _set_turbo(
+      emulator,
+      btn,
+      frames,
+    );
+  }
+
+  late final _set_turboPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Uint32, ffi.Uint8)>>('set_turbo');
+  late final _set_turbo = _set_turboPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, int, int)>();
+
+  bool define_macro(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> name,
+    ffi.Pointer<ffi.Char> steps,
+  ) {
+    return _define_macro(
+      emulator,
+      name,
+      steps,
+    );
+  }
+
+  late final _define_macroPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
+              ffi.Pointer<ffi.Char>)>>('define_macro');
+  late final _define_macro = _define_macroPtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
+          ffi.Pointer<ffi.Char>)>();
+
+  bool trigger_macro(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> name,
+  ) {
+    return _trigger_macro(
+      emulator,
+      name,
+    );
+  }
+
+  late final _trigger_macroPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<Emulator_C>,
+              ffi.Pointer<ffi.Char>)>>('trigger_macro');
+  late final _trigger_macro = _trigger_macroPtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>();
+
+  void set_camera_frame(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Uint8> gray,
+  ) {
+    return _set_camera_frame(
+      emulator,
+      gray,
+    );
+  }
+
+  late final _set_camera_framePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(ffi.Pointer<Emulator_C>,
+              ffi.Pointer<ffi.Uint8>)>>('set_camera_frame');
+  late final _set_camera_frame = _set_camera_framePtr.asFunction<
+      void Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Uint8>)>();
+
   void pause_emulator(
     ffi.Pointer<Emulator_C> emulator,
   ) {
@@ -131,6 +486,467 @@ class NativeBinding {
   late final _resume_emulator =
       _resume_emulatorPtr.asFunction<void Function(ffi.Pointer<Emulator_C>)>();
 
+  bool flush_save(
+    ffi.Pointer<Emulator_C> emulator,
+  ) {
+    return _flush_save(
+      emulator,
+    );
+  }
+
+  late final _flush_savePtr =
+      _lookup<ffi.NativeFunction<ffi.Bool Function(ffi.Pointer<Emulator_C>)>>(
+          'flush_save');
+  late final _flush_save =
+      _flush_savePtr.asFunction<bool Function(ffi.Pointer<Emulator_C>)>();
+
+  bool import_save(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> sav_path,
+  ) {
+    return _import_save(
+      emulator,
+      sav_path,
+    );
+  }
+
+  late final _import_savePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(
+              ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>>('import_save');
+  late final _import_save = _import_savePtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>();
+
+  bool export_save(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> sav_path,
+  ) {
+    return _export_save(
+      emulator,
+      sav_path,
+    );
+  }
+
+  late final _export_savePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(
+              ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>>('export_save');
+  late final _export_save = _export_savePtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>();
+
+  bool add_cheat(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> code,
+    ffi.Pointer<ffi.Char> name,
+  ) {
+    return _add_cheat(
+      emulator,
+      code,
+      name,
+    );
+  }
+
+  late final _add_cheatPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
+              ffi.Pointer<ffi.Char>)>>('add_cheat');
+  late final _add_cheat = _add_cheatPtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
+          ffi.Pointer<ffi.Char>)>();
+
+  bool remove_cheat(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> code,
+  ) {
+    return _remove_cheat(
+      emulator,
+      code,
+    );
+  }
+
+  late final _remove_cheatPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(
+              ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>>('remove_cheat');
+  late final _remove_cheat = _remove_cheatPtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>();
+
+  bool enable_cheat(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> code,
+    bool enabled,
+  ) {
+    return _enable_cheat(
+      emulator,
+      code,
+      enabled,
+    );
+  }
+
+  late final _enable_cheatPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
+              ffi.Bool)>>('enable_cheat');
+  late final _enable_cheat = _enable_cheatPtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>, bool)>();
+
+  ffi.Pointer<ffi.Char> list_cheats(
+    ffi.Pointer<Emulator_C> emulator,
+  ) {
+    return _list_cheats(
+      emulator,
+    );
+  }
+
+  late final _list_cheatsPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Pointer<ffi.Char> Function(
+              ffi.Pointer<Emulator_C>)>>('list_cheats');
+  late final _list_cheats = _list_cheatsPtr
+      .asFunction<ffi.Pointer<ffi.Char> Function(ffi.Pointer<Emulator_C>)>();
+
+  void free_cheat_list(
+    ffi.Pointer<ffi.Char> list,
+  ) {
+    return _free_cheat_list(
+      list,
+    );
+  }
+
+  late final _free_cheat_listPtr =
+      _lookup<ffi.NativeFunction<ffi.Void Function(ffi.Pointer<ffi.Char>)>>(
+          'free_cheat_list');
+  late final _free_cheat_list =
+      _free_cheat_listPtr.asFunction<void Function(ffi.Pointer<ffi.Char>)>();
+
+  void record_movie(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> movie_path,
+  ) {
+    return _record_movie(
+      emulator,
+      movie_path,
+    );
+  }
+
+  late final _record_moviePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>>('record_movie');
+  late final _record_movie = _record_moviePtr.asFunction<
+      void Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>();
+
+  void play_movie(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> movie_path,
+  ) {
+    return _play_movie(
+      emulator,
+      movie_path,
+    );
+  }
+
+  late final _play_moviePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>>('play_movie');
+  late final _play_movie = _play_moviePtr.asFunction<
+      void Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>();
+
+  bool stop_movie(
+    ffi.Pointer<Emulator_C> emulator,
+  ) {
+    return _stop_movie(
+      emulator,
+    );
+  }
+
+  late final _stop_moviePtr =
+      _lookup<ffi.NativeFunction<ffi.Bool Function(ffi.Pointer<Emulator_C>)>>(
+          'stop_movie');
+  late final _stop_movie =
+      _stop_moviePtr.asFunction<bool Function(ffi.Pointer<Emulator_C>)>();
+
+  void set_channel_volume(
+    ffi.Pointer<Emulator_C> emulator,
+    int channel,
+    double volume,
+  ) {
+    return _set_channel_volume(
+      emulator,
+      channel,
+      volume,
+    );
+  }
+
+  late final _set_channel_volumePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(ffi.Pointer<Emulator_C>, ffi.Int32,
+              ffi.Float)>>('set_channel_volume');
+  late final _set_channel_volume = _set_channel_volumePtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, int, double)>();
+
+  void set_channel_muted(
+    ffi.Pointer<Emulator_C> emulator,
+    int channel,
+    bool muted,
+  ) {
+    return _set_channel_muted(
+      emulator,
+      channel,
+      muted,
+    );
+  }
+
+  late final _set_channel_mutedPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(ffi.Pointer<Emulator_C>, ffi.Int32,
+              ffi.Bool)>>('set_channel_muted');
+  late final _set_channel_muted = _set_channel_mutedPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, int, bool)>();
+
+  void set_master_volume(
+    ffi.Pointer<Emulator_C> emulator,
+    double volume,
+  ) {
+    return _set_master_volume(
+      emulator,
+      volume,
+    );
+  }
+
+  late final _set_master_volumePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Float)>>('set_master_volume');
+  late final _set_master_volume = _set_master_volumePtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, double)>();
+
+  void set_sync_mode(
+    ffi.Pointer<Emulator_C> emulator,
+    int mode,
+  ) {
+    return _set_sync_mode(
+      emulator,
+      mode,
+    );
+  }
+
+  late final _set_sync_modePtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Int32)>>('set_sync_mode');
+  late final _set_sync_mode = _set_sync_modePtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, int)>();
+
+  void set_channel_taps(
+    ffi.Pointer<Emulator_C> emulator,
+    bool enabled,
+  ) {
+    return _set_channel_taps(
+      emulator,
+      enabled,
+    );
+  }
+
+  late final _set_channel_tapsPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Bool)>>('set_channel_taps');
+  late final _set_channel_taps = _set_channel_tapsPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, bool)>();
+
+  int read_channel_tap(
+    ffi.Pointer<Emulator_C> emulator,
+    int channel,
+    ffi.Pointer<ffi.Float> buffer,
+    int len,
+  ) {
+    return _read_channel_tap(
+      emulator,
+      channel,
+      buffer,
+      len,
+    );
+  }
+
+  late final _read_channel_tapPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Size Function(ffi.Pointer<Emulator_C>, ffi.Int32,
+              ffi.Pointer<ffi.Float>, ffi.Size)>>('read_channel_tap');
+  late final _read_channel_tap = _read_channel_tapPtr.asFunction<
+      int Function(
+          ffi.Pointer<Emulator_C>, int, ffi.Pointer<ffi.Float>, int)>();
+
+  int read_audio(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Float> buffer,
+    int len,
+  ) {
+    return _read_audio(
+      emulator,
+      buffer,
+      len,
+    );
+  }
+
+  late final _read_audioPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Size Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Float>,
+              ffi.Size)>>('read_audio');
+  late final _read_audio = _read_audioPtr.asFunction<
+      int Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Float>, int)>();
+
+  bool start_recording(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> path,
+  ) {
+    return _start_recording(
+      emulator,
+      path,
+    );
+  }
+
+  late final _start_recordingPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<Emulator_C>,
+              ffi.Pointer<ffi.Char>)>>('start_recording');
+  late final _start_recording = _start_recordingPtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>)>();
+
+  bool stop_recording(
+    ffi.Pointer<Emulator_C> emulator,
+  ) {
+    return _stop_recording(
+      emulator,
+    );
+  }
+
+  late final _stop_recordingPtr =
+      _lookup<ffi.NativeFunction<ffi.Bool Function(ffi.Pointer<Emulator_C>)>>(
+          'stop_recording');
+  late final _stop_recording =
+      _stop_recordingPtr.asFunction<bool Function(ffi.Pointer<Emulator_C>)>();
+
+  bool reset_emulator(
+    ffi.Pointer<Emulator_C> emulator,
+    bool hard,
+  ) {
+    return _reset_emulator(
+      emulator,
+      hard,
+    );
+  }
+
+  late final _reset_emulatorPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(
+              ffi.Pointer<Emulator_C>, ffi.Bool)>>('reset_emulator');
+  late final _reset_emulator = _reset_emulatorPtr
+      .asFunction<bool Function(ffi.Pointer<Emulator_C>, bool)>();
+
+  bool load_rom(
+    ffi.Pointer<Emulator_C> emulator,
+    ffi.Pointer<ffi.Char> rom_path,
+    ffi.Pointer<ffi.Char> save_path,
+  ) {
+    return _load_rom(
+      emulator,
+      rom_path,
+      save_path,
+    );
+  }
+
+  late final _load_romPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
+              ffi.Pointer<ffi.Char>)>>('load_rom');
+  late final _load_rom = _load_romPtr.asFunction<
+      bool Function(ffi.Pointer<Emulator_C>, ffi.Pointer<ffi.Char>,
+          ffi.Pointer<ffi.Char>)>();
+
+  ffi.Pointer<ffi.Char> get_crash_report(
+    ffi.Pointer<Emulator_C> emulator,
+  ) {
+    return _get_crash_report(
+      emulator,
+    );
+  }
+
+  late final _get_crash_reportPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Pointer<ffi.Char> Function(
+              ffi.Pointer<Emulator_C>)>>('get_crash_report');
+  late final _get_crash_report = _get_crash_reportPtr
+      .asFunction<ffi.Pointer<ffi.Char> Function(ffi.Pointer<Emulator_C>)>();
+
+  void free_crash_report(
+    ffi.Pointer<ffi.Char> report,
+  ) {
+    return _free_crash_report(
+      report,
+    );
+  }
+
+  late final _free_crash_reportPtr =
+      _lookup<ffi.NativeFunction<ffi.Void Function(ffi.Pointer<ffi.Char>)>>(
+          'free_crash_report');
+  late final _free_crash_report =
+      _free_crash_reportPtr.asFunction<void Function(ffi.Pointer<ffi.Char>)>();
+
+  void set_event_port(
+    ffi.Pointer<Emulator_C> emulator,
+    int port,
+    ffi.Pointer<ffi.Void> post_c_object,
+  ) {
+    return _set_event_port(
+      emulator,
+      port,
+      post_c_object,
+    );
+  }
+
+  late final _set_event_portPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(ffi.Pointer<Emulator_C>, ffi.Int64,
+              ffi.Pointer<ffi.Void>)>>('set_event_port');
+  late final _set_event_port = _set_event_portPtr.asFunction<
+      void Function(ffi.Pointer<Emulator_C>, int, ffi.Pointer<ffi.Void>)>();
+
+  void add_breakpoint(
+    ffi.Pointer<Emulator_C> emulator,
+    int pc,
+  ) {
+    return _add_breakpoint(
+      emulator,
+      pc,
+    );
+  }
+
+  late final _add_breakpointPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Void Function(
+              ffi.Pointer<Emulator_C>, ffi.Uint16)>>('add_breakpoint');
+  late final _add_breakpoint = _add_breakpointPtr
+      .asFunction<void Function(ffi.Pointer<Emulator_C>, int)>();
+
+  bool remove_breakpoint(
+    ffi.Pointer<Emulator_C> emulator,
+    int pc,
+  ) {
+    return _remove_breakpoint(
+      emulator,
+      pc,
+    );
+  }
+
+  late final _remove_breakpointPtr = _lookup<
+      ffi.NativeFunction<
+          ffi.Bool Function(
+              ffi.Pointer<Emulator_C>, ffi.Uint16)>>('remove_breakpoint');
+  late final _remove_breakpoint = _remove_breakpointPtr
+      .asFunction<bool Function(ffi.Pointer<Emulator_C>, int)>();
+
   void exit_emulator(
     ffi.Pointer<Emulator_C> emulator,
   ) {
@@ -267,9 +1083,124 @@ abstract class GbBtn {
   static const int SELECT = 128;
 }
 
+abstract class InputSource {
+  static const int TOUCH = 0;
+  static const int GAMEPAD = 1;
+  static const int KEYBOARD = 2;
+}
+
+abstract class EventKind {
+  static const int FRAME_READY = 0;
+  static const int GAME_SAVED = 1;
+  static const int CRASHED = 2;
+  static const int RUMBLE = 3;
+  static const int SERIAL_BYTE = 4;
+  static const int BREAKPOINT = 5;
+  static const int RECORDING_STOPPED = 6;
+}
+
+abstract class OppositePolicy {
+  static const int ALLOW = 0;
+  static const int LAST_WINS = 1;
+  static const int NEUTRAL = 2;
+}
+
+abstract class ScaleMode {
+  static const int NEAREST = 0;
+  static const int INTEGER = 1;
+  static const int BILINEAR = 2;
+}
+
+abstract class FilterKind {
+  static const int FILTER_NONE = 0;
+  static const int SCALE2X = 1;
+  static const int SCALE3X = 2;
+  static const int HQ2X = 3;
+  static const int HQ3X = 4;
+  static const int XBR2X = 5;
+}
+
 class WindowConfig extends ffi.Struct {
   @ffi.Float()
   external double scale_factor;
+
+  @ffi.Uint32()
+  external int width;
+
+  @ffi.Uint32()
+  external int height;
+
+  @ffi.Int32()
+  external int scale_mode;
+
+  @ffi.Int32()
+  external int filter;
+}
+
+class RomInfo extends ffi.Struct {
+  @ffi.Array.multi([17])
+  external ffi.Array<ffi.Char> title;
+
+  @ffi.Uint8()
+  external int cgb_flag;
+
+  @ffi.Uint8()
+  external int sgb_flag;
+
+  @ffi.Uint8()
+  external int old_licensee;
+
+  @ffi.Array.multi([3])
+  external ffi.Array<ffi.Char> new_licensee;
+
+  @ffi.Uint8()
+  external int cart_type;
+
+  @ffi.Array.multi([32])
+  external ffi.Array<ffi.Char> cart_type_name;
+
+  @ffi.Uint32()
+  external int rom_size;
+
+  @ffi.Uint32()
+  external int ram_size;
+
+  @ffi.Uint8()
+  external int version;
+
+  @ffi.Uint8()
+  external int header_checksum;
+
+  @ffi.Uint16()
+  external int global_checksum;
+
+  @ffi.Bool()
+  external bool logo_ok;
+
+  @ffi.Bool()
+  external bool header_checksum_ok;
+
+  @ffi.Bool()
+  external bool global_checksum_ok;
+}
+
+abstract class PixelFormat {
+  static const int ARGB8888 = 0;
+  static const int RGBA8888 = 1;
+  static const int RGB565 = 2;
+  static const int DMG_INDEX = 3;
+}
+
+abstract class AudioChannel {
+  static const int SQUARE1 = 0;
+  static const int SQUARE2 = 1;
+  static const int WAVE = 2;
+  static const int NOISE = 3;
+}
+
+abstract class SyncMode {
+  static const int SYNC_VIDEO = 0;
+  static const int SYNC_AUDIO = 1;
 }
 
 typedef Emulator_C = Emulator;
@@ -437,3 +1368,5 @@ const int WINT_MAX = 2147483647;
 const int SIG_ATOMIC_MIN = -2147483648;
 
 const int SIG_ATOMIC_MAX = 2147483647;
+
+const int AUDIO_SAMPLE_RATE = 48000;
@@ -1,12 +1,31 @@
-use crate::device::emulator::Emulator;
+use crate::core::camera::{CAMERA_H, CAMERA_W};
+use crate::core::header::{self, RomInfo};
+use crate::core::loader::{self, LoadOptions, RomSource};
+use crate::core::mixer::AudioChannel;
+use crate::core::rtc::SyncMode;
+use crate::device::emulator::{Command, Emulator};
+use crate::device::input::{InputSource, OppositePolicy};
 use crate::device::keyboard::GbBtn;
+use crate::device::pixel::PixelFormat;
 use crate::device::window::WindowConfig;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
+use std::fs;
 use std::os::raw::c_char;
-use std::thread::{self, JoinHandle};
+use std::path::{Path, PathBuf};
 
-static mut RUNNING_EMU: Option<JoinHandle<()>> = None;
+/// Convert an enum value passed by the host, an invalid value is logged and None is returned
+fn host_enum<T: TryFrom<u32, Error = u32>>(v: u32, name: &str) -> Option<T> {
+    match T::try_from(v) {
+        Ok(v) => Some(v),
+        Err(v) => {
+            log::error!("Invalid {}: {}", name, v);
+            None
+        }
+    }
+}
 
+/// Create an emulator, it must be released by [exit_emulator]. Emulators are independent of each
+/// other, each one runs the game in its own thread
 #[no_mangle]
 pub extern "C" fn create_emulator(win_config: *const WindowConfig) -> *mut Emulator {
     let win_config = unsafe { &*win_config };
@@ -20,62 +39,584 @@ pub extern "C" fn run_emulator(
     rom_path: *const c_char,
     save_path: *const c_char,
 ) {
-    unsafe {
-        let c_str = CStr::from_ptr(rom_path);
-        let rom_path = c_str.to_str().unwrap();
-        let c_str = CStr::from_ptr(save_path);
-        let save_path = c_str.to_str().unwrap();
-        let emulator = &mut *emulator;
-        if emulator.is_running() {
-            return;
-        }
+    let emulator = unsafe { &mut *emulator };
+    let rom_path = unsafe { CStr::from_ptr(rom_path) }.to_str().unwrap();
+    let save_path = unsafe { CStr::from_ptr(save_path) }.to_str().unwrap();
+    let source = RomSource::Path(PathBuf::from(rom_path));
+    emulator.run(source, save_path.to_string());
+}
 
-        RUNNING_EMU = Some(thread::spawn(move || {
-            emulator.run(rom_path, save_path);
-        }));
+/// Run the rom stored in memory, [data] can also be a .zip or .gz archive of the rom.
+/// The data is copied, so the caller can release it once this function returns
+#[no_mangle]
+pub extern "C" fn run_emulator_from_bytes(
+    emulator: *mut Emulator,
+    data: *const u8,
+    len: usize,
+    save_path: *const c_char,
+) {
+    let emulator = unsafe { &mut *emulator };
+    let source = RomSource::Bytes(unsafe { std::slice::from_raw_parts(data, len) }.to_vec());
+    let save_path = unsafe { CStr::from_ptr(save_path) }.to_str().unwrap();
+    emulator.run(source, save_path.to_string());
+}
+
+/// Parse the header of the rom file (or .zip/.gz archive) at [rom_path] into [info],
+/// returns false if the rom can't be read or is too small to contain a header
+#[no_mangle]
+pub extern "C" fn read_rom_info(rom_path: *const c_char, info: *mut RomInfo) -> bool {
+    let rom_path = unsafe { CStr::from_ptr(rom_path) }.to_str().unwrap();
+    rom_info(RomSource::Path(PathBuf::from(rom_path)), info)
+}
+
+/// Same as [read_rom_info], but the rom is stored in memory
+#[no_mangle]
+pub extern "C" fn read_rom_info_from_bytes(
+    data: *const u8,
+    len: usize,
+    info: *mut RomInfo,
+) -> bool {
+    let data = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+    rom_info(RomSource::Bytes(data), info)
+}
+
+fn rom_info(source: RomSource, info: *mut RomInfo) -> bool {
+    let rom = match loader::load_rom(source, &LoadOptions::default()) {
+        Ok(rom) => rom,
+        Err(err) => {
+            log::error!("{}", err);
+            return false;
+        }
+    };
+    match header::parse(&rom) {
+        Some(v) => {
+            unsafe { info.write(v) };
+            true
+        }
+        None => false,
     }
 }
 
+/// Load roms with bad checksums or non-standard sizes with warnings instead of failing,
+/// call it before [run_emulator]
 #[no_mangle]
-pub extern "C" fn get_window_buffer(emulator: *mut Emulator) -> *const u32 {
+pub extern "C" fn set_lenient_loading(emulator: *mut Emulator, lenient: bool) {
     let emulator = unsafe { &mut *emulator };
-    emulator.get_window_buffer().as_ptr()
+    emulator.set_lenient_loading(lenient);
 }
 
+/// Apply the IPS/BPS/UPS patch at [patch_path] on the next run, pass NULL to use the patch
+/// next to the rom with the same name instead
 #[no_mangle]
-pub extern "C" fn press_button(emulator: *mut Emulator, btn: GbBtn) {
+pub extern "C" fn set_patch_path(emulator: *mut Emulator, patch_path: *const c_char) {
     let emulator = unsafe { &mut *emulator };
+    let path = if patch_path.is_null() {
+        None
+    } else {
+        let c_str = unsafe { CStr::from_ptr(patch_path) };
+        Some(PathBuf::from(c_str.to_str().unwrap()))
+    };
+    emulator.set_patch(path);
+}
+
+/// Get the latest frame. The host must call it from a single thread and stop using the pixels
+/// once it's called again
+#[no_mangle]
+pub extern "C" fn get_window_buffer(emulator: *mut Emulator) -> *const u32 {
+    let emulator = unsafe { &*emulator };
+    // Safety: the host is the single consumer, as required by gb_emu.h
+    unsafe { emulator.get_window_frame() }.0
+}
+
+/// Same as [get_window_buffer], and write the sequence number of the frame to [seq]. The number
+/// grows by one for each new frame and is 0 before the first one, so a frame can be skipped if
+/// it's the same as the last call. The pixels stay unchanged until the next call
+#[no_mangle]
+pub extern "C" fn get_window_frame(emulator: *mut Emulator, seq: *mut u64) -> *const u32 {
+    let emulator = unsafe { &*emulator };
+    // Safety: the host is the single consumer, as required by gb_emu.h
+    let (pixels, frame_seq) = unsafe { emulator.get_window_frame() };
+    unsafe { seq.write(frame_seq) };
+    pixels
+}
+
+/// Copy the native 160x144 frame, before effects and scaling, into [buffer] of [len] bytes in
+/// [format], a [PixelFormat]. Return false if [buffer] is NULL or too small, it needs
+/// 160 * 144 * bytes per pixel
+#[no_mangle]
+pub extern "C" fn copy_native_frame(
+    emulator: *mut Emulator,
+    format: u32,
+    buffer: *mut u8,
+    len: usize,
+) -> bool {
+    let emulator = unsafe { &*emulator };
+    let Some(format) = host_enum::<PixelFormat>(format, "pixel format") else {
+        return false;
+    };
+    if buffer.is_null() {
+        return false;
+    }
+    let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, len) };
+    emulator.copy_native_frame(format, buffer).is_some()
+}
+
+/// Write the last frame to [path] as PNG, at 160x144 or with effects and scaling when [scaled].
+/// With [metadata] the game title and frame number are embedded in tEXt chunks
+#[no_mangle]
+pub extern "C" fn save_screenshot(
+    emulator: *mut Emulator,
+    path: *const c_char,
+    scaled: bool,
+    metadata: bool,
+) -> bool {
+    let emulator = unsafe { &*emulator };
+    let path = unsafe { CStr::from_ptr(path) }.to_str().unwrap();
+    match emulator.save_screenshot(Path::new(path), scaled, metadata) {
+        Ok(_) => true,
+        Err(err) => {
+            log::error!("Write {} error: {}", path, err);
+            false
+        }
+    }
+}
+
+/// Blend each frame with the previous one to imitate the slow response of the LCD, some games
+/// rely on it for flickering transparency. [persistence] is how much of the previous frame is
+/// kept, from 0 (off) to 0.9
+#[no_mangle]
+pub extern "C" fn set_ghosting(emulator: *mut Emulator, persistence: f32) {
+    let emulator = unsafe { &*emulator };
+    emulator.set_ghosting(persistence);
+}
+
+/// Draw a grid between Game Boy pixels, only when the frame is scaled by an integer factor
+#[no_mangle]
+pub extern "C" fn set_pixel_grid(emulator: *mut Emulator, enabled: bool) {
+    let emulator = unsafe { &*emulator };
+    emulator.set_pixel_grid(enabled);
+}
+
+/// Show frames in the green shades of the DMG dot-matrix screen
+#[no_mangle]
+pub extern "C" fn set_dmg_tint(emulator: *mut Emulator, enabled: bool) {
+    let emulator = unsafe { &*emulator };
+    emulator.set_dmg_tint(enabled);
+}
+
+#[no_mangle]
+pub extern "C" fn press_button(emulator: *mut Emulator, btn: GbBtn) {
+    let emulator = unsafe { &*emulator };
     emulator.press_button(btn);
 }
 
 #[no_mangle]
 pub extern "C" fn release_button(emulator: *mut Emulator, btn: GbBtn) {
-    let emulator = unsafe { &mut *emulator };
+    let emulator = unsafe { &*emulator };
     emulator.release_button(btn);
 }
 
+/// Key [code] of [source], an [InputSource], is pressed. Unmapped touch codes are treated as
+/// [GbBtn] values, unmapped codes of other sources are ignored
+#[no_mangle]
+pub extern "C" fn key_down(emulator: *mut Emulator, source: u32, code: u32) {
+    let emulator = unsafe { &*emulator };
+    if let Some(source) = host_enum::<InputSource>(source, "input source") {
+        emulator.key_down(source, code);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn key_up(emulator: *mut Emulator, source: u32, code: u32) {
+    let emulator = unsafe { &*emulator };
+    if let Some(source) = host_enum::<InputSource>(source, "input source") {
+        emulator.key_up(source, code);
+    }
+}
+
+/// Map key [code] of [source] to [buttons], a combination of [GbBtn] bits, 0 removes the mapping
+#[no_mangle]
+pub extern "C" fn map_key(emulator: *mut Emulator, source: u32, code: u32, buttons: u8) {
+    let emulator = unsafe { &*emulator };
+    if let Some(source) = host_enum::<InputSource>(source, "input source") {
+        emulator.map_key(source, code, buttons);
+    }
+}
+
+/// Set how Left+Right and Up+Down held at the same time are handled, [policy] is an
+/// [OppositePolicy]
+#[no_mangle]
+pub extern "C" fn set_opposite_policy(emulator: *mut Emulator, policy: u32) {
+    let emulator = unsafe { &*emulator };
+    if let Some(policy) = host_enum::<OppositePolicy>(policy, "opposite policy") {
+        emulator.set_opposite_policy(policy);
+    }
+}
+
+/// Make [btn], a [GbBtn], pressed and released every [frames] frames while it's held, 0 disables
+/// turbo
+#[no_mangle]
+pub extern "C" fn set_turbo(emulator: *mut Emulator, btn: u32, frames: u8) {
+    let emulator = unsafe { &*emulator };
+    if let Some(btn) = host_enum::<GbBtn>(btn, "button") {
+        emulator.set_turbo(btn, frames);
+    }
+}
+
+/// Define a macro named [name], [steps] are separated by ',', each step is buttons joined by '+'
+/// and the frames they are held, e.g. "A+B+Start+Select:10". Return false if [steps] is invalid
+#[no_mangle]
+pub extern "C" fn define_macro(
+    emulator: *mut Emulator,
+    name: *const c_char,
+    steps: *const c_char,
+) -> bool {
+    let emulator = unsafe { &*emulator };
+    let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
+    let steps = unsafe { CStr::from_ptr(steps) }.to_str().unwrap();
+    emulator.define_macro(name, steps)
+}
+
+/// Run the macro named [name], "soft_reset" is always available. Return false if there is no
+/// such macro
+#[no_mangle]
+pub extern "C" fn trigger_macro(emulator: *mut Emulator, name: *const c_char) -> bool {
+    let emulator = unsafe { &*emulator };
+    let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
+    emulator.trigger_macro(name)
+}
+
+/// Feed a grayscale frame to the Game Boy Camera, [gray] points to 128*112 bytes, one byte per
+/// pixel, row by row, 0 is black and 255 is white. A NULL [gray] is ignored
+#[no_mangle]
+pub extern "C" fn set_camera_frame(emulator: *mut Emulator, gray: *const u8) {
+    let emulator = unsafe { &*emulator };
+    if gray.is_null() {
+        return;
+    }
+    let gray = unsafe { std::slice::from_raw_parts(gray, CAMERA_W * CAMERA_H) };
+    emulator.set_camera_frame(gray);
+}
+
 #[no_mangle]
 pub extern "C" fn pause_emulator(emulator: *mut Emulator) {
-    let emulator = unsafe { &mut *emulator };
+    let emulator = unsafe { &*emulator };
     emulator.pause();
 }
 
 #[no_mangle]
 pub extern "C" fn resume_emulator(emulator: *mut Emulator) {
-    unsafe {
-        if let Some(running_thd) = &RUNNING_EMU {
-            let emulator = &mut *emulator;
-            emulator.resume(running_thd.thread());
+    let emulator = unsafe { &*emulator };
+    emulator.resume();
+}
+
+/// Write battery backed ram to disk immediately, e.g. when the app goes to background. It works
+/// even if the emulator is paused, return false if the game was not saved
+#[no_mangle]
+pub extern "C" fn flush_save(emulator: *mut Emulator) -> bool {
+    let emulator = unsafe { &*emulator };
+    emulator.request(Command::FlushSave).unwrap_or(false)
+}
+
+/// Replace battery backed ram of the running game with a .sav file, which may come from other
+/// emulators. Return false if the file can't be read or the cartridge has no battery
+#[no_mangle]
+pub extern "C" fn import_save(emulator: *mut Emulator, sav_path: *const c_char) -> bool {
+    let emulator = unsafe { &*emulator };
+    let sav_path = unsafe { CStr::from_ptr(sav_path) }.to_str().unwrap();
+    let sav = match fs::read(sav_path) {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("Read {} error: {}", sav_path, err);
+            return false;
+        }
+    };
+    emulator
+        .request(|reply| Command::ImportSave(sav, reply))
+        .unwrap_or(false)
+}
+
+/// Export battery backed ram of the running game as a standard .sav file, MBC3 games with a timer
+/// get a 48 bytes RTC footer. Return false if the cartridge has no battery or writing failed
+#[no_mangle]
+pub extern "C" fn export_save(emulator: *mut Emulator, sav_path: *const c_char) -> bool {
+    let emulator = unsafe { &*emulator };
+    let sav_path = unsafe { CStr::from_ptr(sav_path) }.to_str().unwrap();
+    let sav = match emulator.request(Command::ExportSave) {
+        Some(v) if !v.is_empty() => v,
+        _ => return false,
+    };
+    match fs::write(sav_path, sav) {
+        Ok(_) => true,
+        Err(err) => {
+            log::error!("Write {} error: {}", sav_path, err);
+            false
         }
     }
 }
 
+/// Add a Game Genie (ABC-DEF or ABC-DEF-GHI) or GameShark (ABCDEFGH) code, it's enabled and
+/// saved to the cheat file of the running game. Return false if the code is invalid, a GameShark
+/// code doesn't write ram (A000-DFFF) or no game is running
 #[no_mangle]
-pub extern "C" fn exit_emulator(emulator: *mut Emulator) {
+pub extern "C" fn add_cheat(
+    emulator: *mut Emulator,
+    code: *const c_char,
+    name: *const c_char,
+) -> bool {
+    let emulator = unsafe { &*emulator };
+    let code = unsafe { CStr::from_ptr(code) }.to_str().unwrap();
+    let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
+    match emulator.add_cheat(code, name) {
+        Ok(_) => true,
+        Err(err) => {
+            log::error!("{}", err);
+            false
+        }
+    }
+}
+
+/// Return false if there is no such cheat
+#[no_mangle]
+pub extern "C" fn remove_cheat(emulator: *mut Emulator, code: *const c_char) -> bool {
+    let emulator = unsafe { &*emulator };
+    let code = unsafe { CStr::from_ptr(code) }.to_str().unwrap();
+    emulator.remove_cheat(code)
+}
+
+/// Return false if there is no such cheat
+#[no_mangle]
+pub extern "C" fn enable_cheat(
+    emulator: *mut Emulator,
+    code: *const c_char,
+    enabled: bool,
+) -> bool {
+    let emulator = unsafe { &*emulator };
+    let code = unsafe { CStr::from_ptr(code) }.to_str().unwrap();
+    emulator.enable_cheat(code, enabled)
+}
+
+/// List cheats of the running game, one cheat each line: `<code>\t<1 or 0>\t<name>`.
+/// The returned string must be released by [free_cheat_list]
+#[no_mangle]
+pub extern "C" fn list_cheats(emulator: *mut Emulator) -> *mut c_char {
+    let emulator = unsafe { &*emulator };
+    let list = emulator.list_cheats().replace('\0', "");
+    CString::new(list).unwrap().into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn free_cheat_list(list: *mut c_char) {
+    if !list.is_null() {
+        let _ = unsafe { CString::from_raw(list) };
+    }
+}
+
+/// Record the joypad state of every frame to [movie_path] from power-on, call it before
+/// [run_emulator]. The movie is saved when [stop_movie] is called or the emulator exits
+#[no_mangle]
+pub extern "C" fn record_movie(emulator: *mut Emulator, movie_path: *const c_char) {
+    let emulator = unsafe { &mut *emulator };
+    let movie_path = unsafe { CStr::from_ptr(movie_path) }.to_str().unwrap();
+    emulator.record_movie(PathBuf::from(movie_path));
+}
+
+/// Play the movie at [movie_path], call it before [run_emulator]. The run reproduces the recorded
+/// one exactly, and battery backed ram isn't saved during the run
+#[no_mangle]
+pub extern "C" fn play_movie(emulator: *mut Emulator, movie_path: *const c_char) {
+    let emulator = unsafe { &mut *emulator };
+    let movie_path = unsafe { CStr::from_ptr(movie_path) }.to_str().unwrap();
+    emulator.play_movie(PathBuf::from(movie_path));
+}
+
+/// Stop recording or playing the movie, return false if no movie is running or saving failed
+#[no_mangle]
+pub extern "C" fn stop_movie(emulator: *mut Emulator) -> bool {
+    let emulator = unsafe { &*emulator };
+    emulator.request(Command::StopMovie).unwrap_or(false)
+}
+
+/// Set the volume of an APU channel from 0.0 to 1.0, applied on top of the game's own mixing
+#[no_mangle]
+pub extern "C" fn set_channel_volume(emulator: *mut Emulator, channel: u32, volume: f32) {
+    let emulator = unsafe { &*emulator };
+    if let Some(channel) = host_enum::<AudioChannel>(channel, "audio channel") {
+        emulator.set_channel_volume(channel, volume);
+    }
+}
+
+/// Mute or unmute an APU channel, its volume is kept
+#[no_mangle]
+pub extern "C" fn set_channel_muted(emulator: *mut Emulator, channel: u32, muted: bool) {
+    let emulator = unsafe { &*emulator };
+    if let Some(channel) = host_enum::<AudioChannel>(channel, "audio channel") {
+        emulator.set_channel_muted(channel, muted);
+    }
+}
+
+/// Set the volume of the final audio output from 0.0 to 1.0
+#[no_mangle]
+pub extern "C" fn set_master_volume(emulator: *mut Emulator, volume: f32) {
+    let emulator = unsafe { &*emulator };
+    emulator.set_master_volume(volume);
+}
+
+/// Choose how emulation keeps the speed of the real Game Boy. [SyncMode::Audio] waits for the
+/// host to play the audio buffer and slightly adjusts the resampling rate to keep it filled, so
+/// sound never crackles. The host must keep reading the audio with [read_audio], otherwise each
+/// write of the APU waits up to 100ms and the game slows down
+#[no_mangle]
+pub extern "C" fn set_sync_mode(emulator: *mut Emulator, mode: u32) {
+    let emulator = unsafe { &*emulator };
+    if let Some(mode) = host_enum::<SyncMode>(mode, "sync mode") {
+        emulator.set_sync_mode(mode);
+    }
+}
+
+/// Keep the output of each channel separately so it can be read by [read_channel_tap]. Channel
+/// output is taken before the volumes set by the host, and at most 1 second of it is kept
+#[no_mangle]
+pub extern "C" fn set_channel_taps(emulator: *mut Emulator, enabled: bool) {
+    let emulator = unsafe { &*emulator };
+    emulator.set_channel_taps(enabled);
+}
+
+/// Move the kept output of [channel] into [buffer] of [len] floats, as interleaved left and right
+/// samples. Return the number of stereo samples moved, 0 if [buffer] is NULL
+#[no_mangle]
+pub extern "C" fn read_channel_tap(
+    emulator: *mut Emulator,
+    channel: u32,
+    buffer: *mut f32,
+    len: usize,
+) -> usize {
+    let emulator = unsafe { &*emulator };
+    let Some(channel) = host_enum::<AudioChannel>(channel, "audio channel") else {
+        return 0;
+    };
+    if buffer.is_null() {
+        return 0;
+    }
+    let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, len) };
+    emulator.read_channel_tap(channel, buffer)
+}
+
+/// Move the audio of the running game into [buffer] of [len] floats, as interleaved left and
+/// right samples at [AUDIO_SAMPLE_RATE]. At most 1 second of audio is kept, the host should read
+/// it regularly. Return the number of stereo samples moved, 0 if [buffer] is NULL
+#[no_mangle]
+pub extern "C" fn read_audio(emulator: *mut Emulator, buffer: *mut f32, len: usize) -> usize {
+    let emulator = unsafe { &*emulator };
+    if buffer.is_null() {
+        return 0;
+    }
+    let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, len) };
+    emulator.read_audio(buffer)
+}
+
+/// Record video and audio of the running game to an uncompressed AVI file at [path], one video
+/// frame per emulated frame. A recording in progress is saved first. Return false if the emulator
+/// is not running or the file can't be created. Recording stops at 1GB, which is about 4 minutes,
+/// and a [EventKind::RecordingStopped] event is posted
+#[no_mangle]
+pub extern "C" fn start_recording(emulator: *mut Emulator, path: *const c_char) -> bool {
+    let emulator = unsafe { &*emulator };
+    let path = unsafe { CStr::from_ptr(path) }.to_str().unwrap();
+    emulator
+        .request(|reply| Command::StartRecording(PathBuf::from(path), reply))
+        .unwrap_or(false)
+}
+
+/// Stop recording and save the file, return false if nothing is recorded or saving failed
+#[no_mangle]
+pub extern "C" fn stop_recording(emulator: *mut Emulator) -> bool {
+    let emulator = unsafe { &*emulator };
+    emulator.request(Command::StopRecording).unwrap_or(false)
+}
+
+/// Restart the running game, battery backed ram is saved first. A soft reset reuses the loaded
+/// rom, a hard reset reads the rom, its patch and cheats from disk again. A running movie or
+/// recording is stopped. Return false if the emulator is not running or the rom can't be loaded
+#[no_mangle]
+pub extern "C" fn reset_emulator(emulator: *mut Emulator, hard: bool) -> bool {
+    let emulator = unsafe { &*emulator };
+    emulator
+        .request(|reply| Command::Reset(hard, reply))
+        .unwrap_or(false)
+}
+
+/// Save the running game and switch to the rom at [rom_path], [save_path] is the save path of the
+/// new game like in [run_emulator]. The current game keeps running if the rom can't be loaded
+#[no_mangle]
+pub extern "C" fn load_rom(
+    emulator: *mut Emulator,
+    rom_path: *const c_char,
+    save_path: *const c_char,
+) -> bool {
+    let emulator = unsafe { &*emulator };
+    let rom_path = unsafe { CStr::from_ptr(rom_path) }.to_str().unwrap();
+    let save_path = unsafe { CStr::from_ptr(save_path) }.to_str().unwrap();
+    let source = RomSource::Path(PathBuf::from(rom_path));
+    emulator
+        .request(|reply| Command::LoadRom(source, save_path.to_string(), reply))
+        .unwrap_or(false)
+}
+
+/// Why the last run stopped with an error, e.g. the rom failed to load or the emulator crashed,
+/// crashes come with the cpu state. Return NULL if there was no error, otherwise the string must
+/// be released by [free_crash_report]
+#[no_mangle]
+pub extern "C" fn get_crash_report(emulator: *mut Emulator) -> *mut c_char {
+    let emulator = unsafe { &*emulator };
+    match emulator.crash_report() {
+        Some(report) => CString::new(report.replace('\0', "")).unwrap().into_raw(),
+        None => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_crash_report(report: *mut c_char) {
+    if !report.is_null() {
+        let _ = unsafe { CString::from_raw(report) };
+    }
+}
+
+/// Post events of [emulator] to the Dart port [port], each event is a list `[kind, value]`, see
+/// [crate::tools::event::EventSink] for the format. Emulators can post to different ports
+#[no_mangle]
+pub extern "C" fn set_event_port(
+    emulator: *mut Emulator,
+    port: i64,
+    post_c_object: allo_isolate::ffi::DartPostCObjectFnType,
+) {
+    let emulator = unsafe { &*emulator };
     unsafe {
-        (&mut *emulator).exit();
-        // Release emulator object which created by [create_emulator]
-        let _ = Box::from_raw(emulator);
-        RUNNING_EMU = None;
+        allo_isolate::store_dart_post_cobject(post_c_object);
     }
+    emulator.set_event_port(port);
+}
+
+/// Pause the emulator before it executes the instruction at [pc], a breakpoint event is posted.
+/// Resume it with [resume_emulator]
+#[no_mangle]
+pub extern "C" fn add_breakpoint(emulator: *mut Emulator, pc: u16) {
+    let emulator = unsafe { &*emulator };
+    emulator.add_breakpoint(pc);
+}
+
+/// Return false if there is no breakpoint at [pc]
+#[no_mangle]
+pub extern "C" fn remove_breakpoint(emulator: *mut Emulator, pc: u16) -> bool {
+    let emulator = unsafe { &*emulator };
+    emulator.remove_breakpoint(pc)
+}
+
+/// Stop the emulator and release it, it returns after the emulation thread has saved the game
+/// and finished, so [emulator] must not be used any more
+#[no_mangle]
+pub extern "C" fn exit_emulator(emulator: *mut Emulator) {
+    // Release emulator object which created by [create_emulator], the emulation thread is
+    // joined when it's dropped
+    let _ = unsafe { Box::from_raw(emulator) };
 }
@@ -5,7 +5,10 @@ use blip_buf::BlipBuf;
 use crate::core::apu::Channel::{Mixer, Noise, Square1, Square2, Wave};
 use crate::core::convention::CPU_FREQ;
 use crate::core::memory::Memory;
+use crate::core::mixer::MixerControl;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use crate::core::clock::Clock;
 use crate::core::motherboard::MotherBoard;
 
@@ -782,6 +785,25 @@ pub struct APU {
     sample_rate: u32,
     /// 最终要播放的音频数据，包含的采样数据不能大于1s
     pub buffer: Arc<Mutex<Vec<(f32, f32)>>>,
+    /// 录像时收集所有的采样数据，不受播放缓冲区大小的限制
+    pub capture: Option<Vec<(f32, f32)>>,
+    /// 宿主控制的各通道音量和分离输出
+    mixer: MixerControl,
+    /// 是否由音频缓冲区的填充程度控制模拟速度
+    audio_sync: bool,
+}
+
+/// 音频同步时缓冲区中保持的采样时长（毫秒）
+const SYNC_TARGET_MS: u32 = 50;
+/// 音频同步时每次写入最多等待的时长，宿主停止播放时不会一直阻塞模拟线程
+const SYNC_MAX_WAIT: Duration = Duration::from_millis(100);
+/// 动态调整重采样率的最大幅度
+const MAX_RATE_DELTA: f64 = 0.005;
+
+/// 根据缓冲区的填充程度计算重采样率的比例，缓冲区不足时多生成采样，过多时少生成采样
+fn rate_ratio(fill: usize, target: usize) -> f64 {
+    let error = (target as f64 - fill as f64) / target as f64;
+    1.0 + MAX_RATE_DELTA * error.clamp(-1.0, 1.0)
 }
 
 impl APU {
@@ -802,42 +824,74 @@ impl APU {
             noise_channel: ChannelNoise::power_up(buf4),
             sample_rate,
             buffer: Arc::new(Mutex::new(Vec::new())),
+            capture: None,
+            mixer: MixerControl::default(),
+            audio_sync: false,
         }
     }
 
-    pub fn next(&mut self, cycles: u32) {
-        if !self.register.get_power() {
-            return;
+    /// 使用宿主的混音设置
+    pub fn attach_mixer(&mut self, mixer: MixerControl) {
+        self.mixer = mixer;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// 开启后写入采样时等待宿主播放，使模拟速度跟随音频设备的时钟
+    pub fn set_audio_sync(&mut self, on: bool) {
+        self.audio_sync = on;
+        if !on {
+            // 恢复原始的重采样率
+            self.set_rate_ratio(1.0);
+        }
+    }
+
+    fn set_rate_ratio(&mut self, ratio: f64) {
+        let rate = f64::from(self.sample_rate) * ratio;
+        for blip in [
+            &mut self.square1_channel.blip,
+            &mut self.square2_channel.blip,
+            &mut self.wave_channel.blip,
+            &mut self.noise_channel.blip,
+        ] {
+            blip.data.set_rates(f64::from(CPU_FREQ), rate);
         }
+    }
 
+    pub fn next(&mut self, cycles: u32) {
         for _ in 0..self.timer.next(cycles) {
             // 开始一帧采样，让各个音频通道写入音频数据
+            // 关闭电源时所有通道都没有触发，只输出静音，宿主的播放和音频同步不会中断
             self.square1_channel.next(self.timer.period);
             self.square2_channel.next(self.timer.period);
             self.wave_channel.next(self.timer.period);
             self.noise_channel.next(self.timer.period);
 
-            let step = self.fs.next();
-            if step == 0 || step == 2 || step == 4 {
-                // 触发长度控制器
-                self.square1_channel.lc.next();
-                self.square2_channel.lc.next();
-                self.wave_channel.lc.next();
-                self.noise_channel.lc.next();
-            }
+            if self.register.get_power() {
+                let step = self.fs.next();
+                if step == 0 || step == 2 || step == 4 {
+                    // 触发长度控制器
+                    self.square1_channel.lc.next();
+                    self.square2_channel.lc.next();
+                    self.wave_channel.lc.next();
+                    self.noise_channel.lc.next();
+                }
 
-            if step == 7 {
-                // 触发音量包络
-                self.square1_channel.ve.next();
-                self.square2_channel.ve.next();
-                self.noise_channel.ve.next();
-            }
+                if step == 7 {
+                    // 触发音量包络
+                    self.square1_channel.ve.next();
+                    self.square2_channel.ve.next();
+                    self.noise_channel.ve.next();
+                }
 
-            if step == 2 || step == 6 {
-                // 触发扫频器
-                self.square1_channel.fs.next();
-                // 更新通道频率
-                self.square1_channel.update_freq();
+                if step == 2 || step == 6 {
+                    // 触发扫频器
+                    self.square1_channel.fs.next();
+                    // 更新通道频率
+                    self.square1_channel.update_freq();
+                }
             }
 
             let duration = self.timer.period;
@@ -864,6 +918,11 @@ impl APU {
         let l_vol = (f32::from(self.register.get_l_vol()) / 7.0) * factor;
         let r_vol = (f32::from(self.register.get_r_vol()) / 7.0) * factor;
 
+        // 宿主设置的各通道音量
+        let gains = self.mixer.gains();
+        let taps_enabled = self.mixer.taps_enabled();
+        let limit = self.sample_rate as usize;
+
         while sum < sample_size {
             // 左声道数据
             let buf_l = &mut [0f32; 2048];
@@ -871,77 +930,69 @@ impl APU {
             let buf_r = &mut [0f32; 2048];
             // 从音频通道中读取的数据
             let buf = &mut [0i16; 2048];
-            // nr51寄存器，记录各个音频通道的左右声道是否可用
+            // nr51寄存器，高4位控制各个通道是否输出到左声道，低4位控制右声道
+            // 每4位中从低到高依次是Square1, Square2, Wave, Noise通道
             let nr51 = self.register.nrx1;
-            // 左右声道混入当前音频的数据
-            let mut mix_data = |count, enable_l, enable_r, buf: &[i16]| {
-                for (i, v) in buf[..count].iter().enumerate() {
-                    if enable_l {
-                        // 左声道混入当前音频通道的数据
-                        buf_l[i] += f32::from(*v) * l_vol;
-                    }
-                    if enable_r {
-                        // 右声道混入当前音频通道的数据
-                        buf_r[i] += f32::from(*v) * r_vol;
-                    }
+            let blips = [
+                &mut self.square1_channel.blip,
+                &mut self.square2_channel.blip,
+                &mut self.wave_channel.blip,
+                &mut self.noise_channel.blip,
+            ];
+            let mut count = 0;
+            for (i, blip) in blips.into_iter().enumerate() {
+                // 所有通道读取的样本数都相同
+                count = blip.data.read_samples(buf, false);
+                let l_vol = if nr51 & (0x10 << i) != 0 { l_vol } else { 0.0 };
+                let r_vol = if nr51 & (0x01 << i) != 0 { r_vol } else { 0.0 };
+                let samples = buf[..count]
+                    .iter()
+                    .map(|v| (f32::from(*v) * l_vol, f32::from(*v) * r_vol));
+                if taps_enabled {
+                    // 分离出的通道数据不受宿主音量的影响
+                    self.mixer.push_tap(i, samples.clone(), limit);
                 }
-            };
-
-            // 读取Square1通道的数据
-            let s1_count = self.square1_channel.blip.data.read_samples(buf, false);
-            // Square1通道左声道是否可用
-            let s1_enable_l = nr51 & 0x01 == 0x01;
-            // Square1通道右声道是否可用
-            let s1_enable_r = nr51 & 0x10 == 0x10;
-            // 左右声道混入Square1通道的数据
-            mix_data(s1_count, s1_enable_l, s1_enable_r, buf);
-
-
-            // 读取Square2通道中的数据
-            let s2_count = self.square2_channel.blip.data.read_samples(buf, false);
-            assert_eq!(s2_count, s1_count);
-            // Square2通道左声道是否可用
-            let s2_enable_l = nr51 & 0x02 == 0x02;
-            // Square2通道右声道是否可用
-            let s2_enable_r = nr51 & 0x20 == 0x20;
-            // 左右声道混入Square2通道的数据
-            mix_data(s2_count, s2_enable_l, s2_enable_r, buf);
-
-            // 读取Wave通道中的数据
-            let w_count = self.wave_channel.blip.data.read_samples(buf, false);
-            // Wave通道左声道是否可用
-            let w_enable_l = nr51 & 0x04 == 0x04;
-            // Wave通道右声道是否可用
-            let w_enable_r = nr51 & 0x40 == 0x40;
-            // 左右声道混入wave通道的数据
-            mix_data(w_count, w_enable_l, w_enable_r, buf);
-
-            // 读取Noise通道中的数据
-            let n_count = self.noise_channel.blip.data.read_samples(buf, false);
-            // Noise通道左声道是否可用
-            let n_enable_l = nr51 & 0x04 == 0x04;
-            // Noise通道右声道是否可用
-            let n_enable_r = nr51 & 0x40 == 0x40;
-            // 左右声道混入Noise通道的数据
-            mix_data(n_count, n_enable_l, n_enable_r, buf);
+                // 左右声道混入当前音频通道的数据
+                for (j, (l, r)) in samples.enumerate() {
+                    buf_l[j] += l * gains[i];
+                    buf_r[j] += r * gains[i];
+                }
+            }
 
             // 写入最终混合好的音频数据
-            self.play(buf_l, buf_r);
-            sum += s1_count as u32;
+            self.play(&buf_l[..count], &buf_r[..count]);
+            sum += count as u32;
         }
     }
 
     /// 写入最终要播放的音频数据
     fn play(&mut self, l: &[f32], r: &[f32]) {
         assert_eq!(l.len(), r.len());
+        if let Some(capture) = &mut self.capture {
+            capture.extend(l.iter().copied().zip(r.iter().copied()));
+        }
+        let target = (self.sample_rate * SYNC_TARGET_MS / 1000) as usize;
+        if self.audio_sync {
+            // 缓冲区中的采样足够时等待宿主播放，模拟速度由此与音频设备保持一致
+            let deadline = Instant::now() + SYNC_MAX_WAIT;
+            while self.buffer.lock().unwrap().len() > target && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
         let mut buffer = self.buffer.lock().unwrap();
         for (lv, rv) in l.iter().zip(r) {
             if buffer.len() > self.sample_rate as usize {
                 // 不能写入大于1s的采样数据
-                return;
+                break;
             }
             buffer.push((*lv, *rv));
         }
+        if self.audio_sync {
+            // 音频设备与模拟的时钟有微小的差异，通过微调重采样率让缓冲区保持在目标大小附近
+            let ratio = rate_ratio(buffer.len(), target);
+            drop(buffer);
+            self.set_rate_ratio(ratio);
+        }
     }
 }
 
@@ -1108,3 +1159,90 @@ pub fn initialize_audio(mbrd: &MotherBoard) {
     });
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::loader::LoadOptions;
+    use crate::core::mixer::AudioChannel;
+    use crate::core::rtc::SyncMode;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_rate_ratio() {
+        assert_eq!(rate_ratio(100, 100), 1.0);
+        assert_eq!(rate_ratio(0, 100), 1.005);
+        assert_eq!(rate_ratio(1000, 100), 0.995);
+    }
+
+    #[test]
+    fn test_mixer_mute() {
+        let mut apu = APU::power_up(48000);
+        let mixer = MixerControl::default();
+        apu.attach_mixer(mixer.clone());
+        // 打开电源，Square1以最大音量输出到两个声道
+        let registers = [
+            (0xff26, 0x80),
+            (0xff24, 0x77),
+            (0xff25, 0x11),
+            (0xff11, 0x80),
+            (0xff12, 0xf0),
+            (0xff14, 0x87),
+        ];
+        for (a, v) in registers {
+            apu.set(a, v);
+        }
+        apu.next(CPU_FREQ / 64);
+        let samples = std::mem::take(&mut *apu.buffer.lock().unwrap());
+        assert_eq!(samples.len(), 48000 / 64);
+        assert!(samples.iter().any(|(l, r)| *l != 0.0 && *r != 0.0));
+
+        mixer.set_muted(AudioChannel::Square1, true);
+        apu.next(CPU_FREQ / 64);
+        let samples = apu.buffer.lock().unwrap();
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|(l, r)| *l == 0.0 && *r == 0.0));
+    }
+
+    #[test]
+    fn test_audio_sync() {
+        // 只有一条跳转到自身的指令，APU没有打开电源，只输出静音
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xfe]);
+        let options = LoadOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let mut mbrd = MotherBoard::power_up(rom, "", &options);
+        let apu = APU::power_up(48000);
+        let buffer = apu.buffer.clone();
+        mbrd.mmu.borrow_mut().apu = Some(apu);
+        mbrd.set_sync(SyncMode::Audio);
+
+        // 宿主以一半的速度播放，模拟速度应该随之减慢
+        let stop = Arc::new(AtomicBool::new(false));
+        let player = {
+            let stop = stop.clone();
+            let buffer = buffer.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(5));
+                    let mut buffer = buffer.lock().unwrap();
+                    let count = buffer.len().min(120);
+                    buffer.drain(..count);
+                }
+            })
+        };
+        let start = Instant::now();
+        let mut cycles = 0;
+        while cycles < CPU_FREQ / 4 {
+            cycles += mbrd.next();
+        }
+        let elapsed = start.elapsed();
+        stop.store(true, Ordering::Relaxed);
+        player.join().unwrap();
+
+        // 0.25s的模拟时间需要播放0.5s，缓冲区中最多提前50ms的采样
+        assert!(elapsed >= Duration::from_millis(400), "{:?}", elapsed);
+    }
+}
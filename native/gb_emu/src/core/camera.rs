@@ -0,0 +1,332 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::core::cartridge::{export_ram, import_ram, restore_ram, save_ram, Cartridge, Stable};
+use crate::core::memory::Memory;
+
+/// The width of image captured by the camera sensor
+pub const CAMERA_W: usize = 128;
+/// The height of image captured by the camera sensor
+pub const CAMERA_H: usize = 112;
+
+/// Offset of the captured image in ram bank 0
+const IMAGE_OFFSET: usize = 0x0100;
+/// Number of sensor registers, A000-A035
+const REG_COUNT: usize = 0x36;
+/// Index of the first dithering threshold register
+const DITHER_BASE: usize = 0x06;
+
+/// Gain values selected by bit 0-4 of register A001
+const GAIN_VALUES: [f64; 32] = [
+    0.8809390, 0.9149149, 0.9457498, 0.9739758, 1.0000000, 1.0241412, 1.0466537, 1.0677433,
+    1.0875793, 1.1240310, 1.1568911, 1.1868043, 1.2142561, 1.2396208, 1.2743837, 1.3157323,
+    1.3525190, 1.3856512, 1.4157897, 1.4434309, 1.4689574, 1.4926697, 1.5148087, 1.5355703,
+    1.5551159, 1.5735801, 1.5910762, 1.6077008, 1.6235366, 1.6386550, 1.6531183, 1.6669812,
+];
+
+/// Edge enhancement ratios selected by bit 4-6 of register A004
+const EDGE_RATIOS: [f64; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+
+/// The image source of the camera sensor, shared between the host and the emulation thread.
+/// The host writes 128x112 grayscale frames (0 is black, 255 is white) into it, and the cartridge
+/// reads a frame each time the game starts a capture
+#[derive(Clone, Default)]
+pub struct CameraSource {
+    frame: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl CameraSource {
+    /// Replace the current frame, [gray] must contain at least 128*112 bytes
+    pub fn set_frame(&self, gray: &[u8]) {
+        let mut frame = self.frame.lock().unwrap();
+        *frame = Some(gray[..CAMERA_W * CAMERA_H].to_vec());
+    }
+
+    /// Take a copy of the current frame, fill with noise if the host never supplied one
+    fn snapshot(&self) -> Vec<u8> {
+        match self.frame.lock().unwrap().as_ref() {
+            Some(frame) => frame.clone(),
+            None => (0..CAMERA_W * CAMERA_H)
+                .map(|i| noise(i % CAMERA_W, i / CAMERA_W))
+                .collect(),
+        }
+    }
+}
+
+/// Generate a stable noise pattern, it's what the sensor sees without a host image source
+fn noise(x: usize, y: usize) -> u8 {
+    let mut v = (x as u32).wrapping_mul(0x9e37_79b9) ^ (y as u32).wrapping_mul(0x85eb_ca6b);
+    v ^= v >> 15;
+    v = v.wrapping_mul(0x2c1b_3c6d);
+    v ^= v >> 12;
+    (v & 0xff) as u8
+}
+
+/// Game Boy Camera (POCKET CAMERA) cartridge, the mapper is similar to MBC3 without RTC, and the
+/// M64282FP image sensor registers are mapped to A000-A035 when bit 4 of the ram bank is set
+pub struct PocketCamera {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_bank: usize,
+    ram_bank: usize,
+    ram_enable: bool,
+    /// Whether sensor registers are mapped to A000-BFFF instead of ram
+    reg_mode: bool,
+    /// Sensor registers
+    /// A000: bit 0 starts a capture and reads 1 while capturing
+    /// A001: bit 0-4 gain, bit 5-7 edge enhancement mode
+    /// A002-A003: exposure time, high byte first
+    /// A004: bit 4-6 edge enhancement ratio
+    /// A006-A035: 4x4 dithering matrix, 3 thresholds for each pixel
+    regs: [u8; REG_COUNT],
+    source: CameraSource,
+    dirty: bool,
+    save_path: PathBuf,
+}
+
+impl PocketCamera {
+    pub fn power_up(rom: Vec<u8>, ram: Vec<u8>, sav: impl AsRef<Path>) -> Self {
+        Self {
+            rom,
+            ram,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enable: false,
+            reg_mode: false,
+            regs: [0; REG_COUNT],
+            source: CameraSource::default(),
+            dirty: false,
+            save_path: PathBuf::from(sav.as_ref()),
+        }
+    }
+
+    /// The brightness of sensor pixel (x, y) after applying gain and exposure
+    fn pixel(&self, frame: &[u8], x: isize, y: isize) -> f64 {
+        // Pixels outside the sensor wrap to the opposite edge
+        let x = x.rem_euclid(CAMERA_W as isize) as usize;
+        let y = y.rem_euclid(CAMERA_H as isize) as usize;
+        let exposure = (u32::from(self.regs[0x02]) << 8) | u32::from(self.regs[0x03]);
+        let v = f64::from(frame[y * CAMERA_W + x]) * GAIN_VALUES[(self.regs[0x01] & 0x1f) as usize];
+        v * f64::from(exposure) / f64::from(0x1000)
+    }
+
+    /// Capture an image from the source and write it to ram as 16x14 tiles
+    fn capture(&mut self) {
+        let frame = self.source.snapshot();
+        let edge = (self.regs[0x01] & 0xe0) == 0xe0;
+        let ratio = EDGE_RATIOS[((self.regs[0x04] >> 4) & 0x07) as usize];
+
+        for y in 0..CAMERA_H {
+            for x in 0..CAMERA_W {
+                let (ix, iy) = (x as isize, y as isize);
+                let mut v = self.pixel(&frame, ix, iy);
+                if edge {
+                    v += v * 4.0 * ratio;
+                    v -= self.pixel(&frame, ix - 1, iy) * ratio;
+                    v -= self.pixel(&frame, ix + 1, iy) * ratio;
+                    v -= self.pixel(&frame, ix, iy - 1) * ratio;
+                    v -= self.pixel(&frame, ix, iy + 1) * ratio;
+                }
+                // Dithering matrix is used as thresholds to convert brightness to color number
+                let base = DITHER_BASE + ((x & 3) + (y & 3) * 4) * 3;
+                let color: u8 = if v < f64::from(self.regs[base]) {
+                    3
+                } else if v < f64::from(self.regs[base + 1]) {
+                    2
+                } else if v < f64::from(self.regs[base + 2]) {
+                    1
+                } else {
+                    0
+                };
+
+                let tile = (y / 8) * (CAMERA_W / 8) + x / 8;
+                let i = IMAGE_OFFSET + tile * 16 + (y % 8) * 2;
+                if i + 1 >= self.ram.len() {
+                    return;
+                }
+                self.dirty = true;
+                let mask = 0x80 >> (x % 8);
+                for (b, bit) in [(i, 0x01), (i + 1, 0x02)] {
+                    if color & bit != 0 {
+                        self.ram[b] |= mask;
+                    } else {
+                        self.ram[b] &= !mask;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Memory for PocketCamera {
+    fn get(&self, a: u16) -> u8 {
+        match a {
+            0x0000..=0x3fff => self.rom[a as usize],
+            0x4000..=0x7fff => {
+                let i = self.rom_bank * 0x4000 + a as usize - 0x4000;
+                self.rom[i % self.rom.len()]
+            }
+            0xa000..=0xbfff => {
+                if self.reg_mode {
+                    // Only A000 is readable, the capture is done as soon as it starts
+                    return if (a - 0xa000) & 0x7f == 0 {
+                        self.regs[0] & 0x06
+                    } else {
+                        0x00
+                    };
+                }
+                let i = self.ram_bank * 0x2000 + a as usize - 0xa000;
+                self.ram.get(i).copied().unwrap_or(0x00)
+            }
+            _ => 0x00,
+        }
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        match a {
+            0x0000..=0x1fff => self.ram_enable = v & 0x0f == 0x0a,
+            0x2000..=0x3fff => self.rom_bank = (v & 0x3f) as usize,
+            0x4000..=0x5fff => {
+                self.reg_mode = v & 0x10 != 0;
+                self.ram_bank = (v & 0x0f) as usize;
+            }
+            0xa000..=0xbfff => {
+                if self.reg_mode {
+                    let r = ((a - 0xa000) & 0x7f) as usize;
+                    if r >= REG_COUNT {
+                        return;
+                    }
+                    self.regs[r] = v;
+                    if r == 0 && v & 0x01 != 0 {
+                        self.capture();
+                        // Capture finished, clear the busy flag
+                        self.regs[0] &= !0x01;
+                    }
+                    return;
+                }
+                if !self.ram_enable {
+                    return;
+                }
+                let i = self.ram_bank * 0x2000 + a as usize - 0xa000;
+                if let Some(b) = self.ram.get_mut(i) {
+                    self.dirty |= *b != v;
+                    *b = v;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Stable for PocketCamera {
+    fn save(&mut self) -> bool {
+        save_ram(&self.save_path, &self.ram, &mut self.dirty)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn export_sav(&self) -> Vec<u8> {
+        export_ram(&self.save_path, &self.ram)
+    }
+
+    fn import_sav(&mut self, sav: &[u8]) -> bool {
+        import_ram(&self.save_path, &mut self.ram, sav, &mut self.dirty).is_some()
+    }
+
+    fn restore_sav(&mut self, sav: &[u8]) {
+        restore_ram(&self.save_path, &mut self.ram, sav);
+    }
+}
+
+impl Cartridge for PocketCamera {
+    fn attach_camera(&mut self, source: CameraSource) {
+        self.source = source;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A camera with 4 rom banks filled with their bank number and 4 ram banks
+    fn camera() -> PocketCamera {
+        let rom = (0..4u8).flat_map(|bank| [bank; 0x4000]).collect();
+        PocketCamera::power_up(rom, vec![0; 0x8000], "")
+    }
+
+    /// Map the sensor registers, set gain to 1.0 and exposure to 0x1000 so pixels keep their
+    /// brightness, and set the thresholds of each position of the dithering matrix
+    fn setup_capture(cam: &mut PocketCamera, gray: u8, thresholds: impl Fn(usize) -> [u8; 3]) {
+        cam.source.set_frame(&[gray; CAMERA_W * CAMERA_H]);
+        cam.set(0x4000, 0x10);
+        cam.set(0xa001, 0x04);
+        cam.set(0xa002, 0x10);
+        cam.set(0xa003, 0x00);
+        for p in 0..16 {
+            for (i, v) in thresholds(p).into_iter().enumerate() {
+                cam.set(0xa006 + (p * 3 + i) as u16, v);
+            }
+        }
+    }
+
+    #[test]
+    fn test_banking() {
+        let mut cam = camera();
+        assert_eq!(cam.get(0x4000), 1);
+        cam.set(0x2000, 0x03);
+        assert_eq!(cam.get(0x7fff), 3);
+
+        // ram is only written when enabled
+        cam.set(0x4000, 0x01);
+        cam.set(0xa000, 0x55);
+        assert_eq!(cam.get(0xa000), 0x00);
+        cam.set(0x0000, 0x0a);
+        cam.set(0xa000, 0x55);
+        cam.set(0x4000, 0x00);
+        assert_eq!(cam.get(0xa000), 0x00);
+        cam.set(0x4000, 0x01);
+        assert_eq!(cam.get(0xa000), 0x55);
+
+        // Bit 4 maps the registers, which don't touch ram
+        cam.set(0x4000, 0x11);
+        cam.set(0xa000, 0x06);
+        cam.set(0xa001, 0xff);
+        assert_eq!(cam.get(0xa000), 0x06);
+        assert_eq!(cam.get(0xa001), 0x00);
+        assert_eq!(cam.regs[1], 0xff);
+        cam.set(0x4000, 0x01);
+        assert_eq!(cam.get(0xa000), 0x55);
+    }
+
+    #[test]
+    fn test_capture() {
+        let mut cam = camera();
+        setup_capture(&mut cam, 0x90, |_| [0x40, 0x80, 0xc0]);
+        assert!(!cam.is_dirty());
+        cam.set(0xa000, 0x01);
+        // The capture finishes at once and the busy flag is cleared
+        assert_eq!(cam.get(0xa000), 0x00);
+        assert!(cam.is_dirty());
+        // 0x90 is between the second and the third threshold, every pixel is color 1
+        let image = &cam.ram[IMAGE_OFFSET..IMAGE_OFFSET + CAMERA_W * CAMERA_H / 4];
+        assert!(image.chunks(2).all(|row| row == [0xff, 0x00]));
+    }
+
+    #[test]
+    fn test_dithering() {
+        let mut cam = camera();
+        // Each column of the matrix turns the same brightness into another color
+        setup_capture(&mut cam, 0x80, |p| match p % 4 {
+            0 => [0xff, 0xff, 0xff],
+            1 => [0x00, 0xff, 0xff],
+            2 => [0x00, 0x00, 0xff],
+            _ => [0x00, 0x00, 0x00],
+        });
+        cam.set(0xa000, 0x01);
+        // Colors 3, 2, 1, 0 repeated in every row of every tile
+        let image = &cam.ram[IMAGE_OFFSET..IMAGE_OFFSET + CAMERA_W * CAMERA_H / 4];
+        assert!(image.chunks(2).all(|row| row == [0xaa, 0xcc]));
+    }
+}
@@ -1,6 +1,10 @@
+use crate::core::camera::{CameraSource, PocketCamera};
 use crate::core::convention::Term;
+use crate::core::header;
+use crate::core::loader::LoadOptions;
 use crate::core::memory::Memory;
-use std::fs::{create_dir_all, File};
+use crate::core::rtc::CLOCK_FREQUENCY;
+use std::fs::{self, create_dir_all, File};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
@@ -10,7 +14,27 @@ pub struct RomOnly {
 }
 
 pub trait Stable {
-    fn save(&self);
+    /// Write battery backed data to disk if it changed since last save, return false if failed
+    fn save(&mut self) -> bool;
+
+    /// Whether battery backed data changed since last save
+    fn is_dirty(&self) -> bool {
+        false
+    }
+
+    /// Export battery backed data in the .sav format shared by most emulators, it's empty if the
+    /// cartridge has no battery
+    fn export_sav(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    /// Replace battery backed data with a .sav file, return false if the cartridge has no battery
+    fn import_sav(&mut self, _sav: &[u8]) -> bool {
+        false
+    }
+
+    /// Load a .sav file into memory without writing it to disk, used by movie playback
+    fn restore_sav(&mut self, _sav: &[u8]) {}
 }
 
 impl RomOnly {
@@ -30,7 +54,9 @@ impl Memory for RomOnly {
 }
 
 impl Stable for RomOnly {
-    fn save(&self) {}
+    fn save(&mut self) -> bool {
+        true
+    }
 }
 
 enum BankMod {
@@ -44,6 +70,7 @@ pub struct Mbc1 {
     bank_mode: BankMod,
     bank: u8,
     ram_enable: bool,
+    dirty: bool,
     save_path: PathBuf,
 }
 
@@ -55,6 +82,7 @@ impl Mbc1 {
             bank_mode: BankMod::Rom,
             bank: 0x01,
             ram_enable: false,
+            dirty: false,
             save_path: PathBuf::from(sav.as_ref()),
         }
     }
@@ -81,13 +109,14 @@ impl Memory for Mbc1 {
         match a {
             0x0000..=0x3fff => self.rom[a as usize],
             0x4000..=0x7fff => {
+                // rom的大小是2的幂，超出rom容量的bank会像硬件的地址线一样回绕到前面的bank
                 let i = self.rom_bank() * 0x4000 + a as usize - 0x4000;
-                self.rom[i]
+                self.rom[i & (self.rom.len() - 1)]
             }
             0xa000..=0xbfff => {
                 if self.ram_enable {
                     let i = self.ram_bank() * 0x2000 + a as usize - 0xa000;
-                    self.ram[i]
+                    ram_get(&self.ram, i)
                 } else {
                     0x00
                 }
@@ -101,7 +130,7 @@ impl Memory for Mbc1 {
             0xa000..=0xbfff => {
                 if self.ram_enable {
                     let i = self.ram_bank() * 0x2000 + a as usize - 0xa000;
-                    self.ram[i] = v;
+                    self.dirty |= ram_set(&mut self.ram, i, v);
                 }
             }
             0x0000..=0x1fff => {
@@ -129,12 +158,24 @@ impl Memory for Mbc1 {
 }
 
 impl Stable for Mbc1 {
-    fn save(&self) {
-        if self.save_path.to_str().unwrap().is_empty() {
-            return;
-        }
+    fn save(&mut self) -> bool {
+        save_ram(&self.save_path, &self.ram, &mut self.dirty)
+    }
 
-        save_bytes(&self.save_path, &self.ram);
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn export_sav(&self) -> Vec<u8> {
+        export_ram(&self.save_path, &self.ram)
+    }
+
+    fn import_sav(&mut self, sav: &[u8]) -> bool {
+        import_ram(&self.save_path, &mut self.ram, sav, &mut self.dirty).is_some()
+    }
+
+    fn restore_sav(&mut self, sav: &[u8]) {
+        restore_ram(&self.save_path, &mut self.ram, sav);
     }
 }
 
@@ -143,6 +184,7 @@ pub struct Mbc2 {
     ram: Vec<u8>,
     rom_bank: usize,
     ram_enable: bool,
+    dirty: bool,
     save_path: PathBuf,
 }
 
@@ -153,6 +195,7 @@ impl Mbc2 {
             ram,
             rom_bank: 1,
             ram_enable: false,
+            dirty: false,
             save_path: PathBuf::from(sav.as_ref()),
         }
     }
@@ -164,11 +207,11 @@ impl Memory for Mbc2 {
             0x000..=0x3fff => self.rom[a as usize],
             0x4000..=0x7fff => {
                 let i = self.rom_bank * 0x4000 + a as usize - 0x4000;
-                self.rom[i]
+                self.rom[i & (self.rom.len() - 1)]
             }
             0xa000..=0xa1ff => {
                 if self.ram_enable {
-                    self.ram[(a - 0xa000) as usize]
+                    ram_get(&self.ram, (a - 0xa000) as usize)
                 } else {
                     0x00
                 }
@@ -182,7 +225,7 @@ impl Memory for Mbc2 {
         match a {
             0xa000..=0xa1ff => {
                 if self.ram_enable {
-                    self.ram[(a - 0xa000) as usize] = v;
+                    self.dirty |= ram_set(&mut self.ram, (a - 0xa000) as usize, v);
                 }
             }
             0x0000..=0x1fff => {
@@ -201,11 +244,24 @@ impl Memory for Mbc2 {
 }
 
 impl Stable for Mbc2 {
-    fn save(&self) {
-        if self.save_path.to_str().unwrap().is_empty() {
-            return;
-        }
-        save_bytes(&self.save_path, &self.ram)
+    fn save(&mut self) -> bool {
+        save_ram(&self.save_path, &self.ram, &mut self.dirty)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn export_sav(&self) -> Vec<u8> {
+        export_ram(&self.save_path, &self.ram)
+    }
+
+    fn import_sav(&mut self, sav: &[u8]) -> bool {
+        import_ram(&self.save_path, &mut self.ram, sav, &mut self.dirty).is_some()
+    }
+
+    fn restore_sav(&mut self, sav: &[u8]) {
+        restore_ram(&self.save_path, &mut self.ram, sav);
     }
 }
 
@@ -216,22 +272,21 @@ struct RealTimeClock {
     dl: u8,
     dh: u8,
     zero: u64,
+    // zero还没有保存到本地文件
+    dirty: bool,
     save_path: PathBuf,
+    // 模拟的时钟（Unix时间，不足1秒的时钟周期），为None时使用系统时间
+    emulated: Option<(u64, u32)>,
 }
 
 impl RealTimeClock {
     fn power_up(save_path: impl AsRef<Path>) -> Self {
-        let zero = match std::fs::read(save_path.as_ref()) {
-            Ok(v) => {
-                let mut b: [u8; 8] = Default::default();
-                b.copy_from_slice(&v);
-                u64::from_be_bytes(b)
-            }
-            Err(_) => SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        };
+        let saved = read_bytes(save_path.as_ref()).and_then(|v| {
+            let b: [u8; 8] = v.as_slice().try_into().ok()?;
+            Some(u64::from_be_bytes(b))
+        });
+        let dirty = saved.is_none();
+        let zero = saved.unwrap_or_else(now_secs);
         RealTimeClock {
             s: 0,
             m: 0,
@@ -239,16 +294,38 @@ impl RealTimeClock {
             dl: 0,
             dh: 0,
             zero,
+            dirty,
             save_path: save_path.as_ref().to_path_buf(),
+            emulated: None,
+        }
+    }
+
+    /// Current unix time, it's the emulated time if the emulated clock is used
+    fn now(&self) -> u64 {
+        match self.emulated {
+            Some((secs, _)) => secs,
+            None => now_secs(),
+        }
+    }
+
+    /// Elapsed seconds since [zero]
+    fn elapsed(&self) -> u64 {
+        self.now().saturating_sub(self.zero)
+    }
+
+    /// Advance the emulated clock by [cycles]
+    fn next(&mut self, cycles: u32) {
+        if let Some((secs, c)) = &mut self.emulated {
+            *c += cycles;
+            if *c >= CLOCK_FREQUENCY {
+                *c -= CLOCK_FREQUENCY;
+                *secs += 1;
+            }
         }
     }
 
     fn tick(&mut self) {
-        let d = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            - self.zero;
+        let d = self.elapsed();
         self.s = (d % 60) as u8;
         self.m = (d / 60 % 60) as u8;
         self.h = (d / 3600 % 24) as u8;
@@ -270,7 +347,7 @@ impl Memory for RealTimeClock {
             0x0a => self.h,
             0x0b => self.dl,
             0x0c => self.dh,
-            _ => panic!("No entry"),
+            _ => 0xff,
         }
     }
 
@@ -281,17 +358,42 @@ impl Memory for RealTimeClock {
             0x0a => self.h = b,
             0x0b => self.dl = b,
             0x0c => self.dh = b,
-            _ => panic!("No entry"),
+            _ => {}
         }
     }
 }
 
 impl Stable for RealTimeClock {
-    fn save(&self) {
-        if self.save_path.to_str().unwrap().is_empty() {
-            return;
+    fn save(&mut self) -> bool {
+        save_ram(&self.save_path, &self.zero.to_be_bytes(), &mut self.dirty)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn export_sav(&self) -> Vec<u8> {
+        if self.save_path.as_os_str().is_empty() {
+            return vec![];
+        }
+        rtc_footer(self.elapsed(), self.now())
+    }
+
+    fn import_sav(&mut self, sav: &[u8]) -> bool {
+        match rtc_footer_zero(sav) {
+            Some(zero) => {
+                self.zero = zero;
+                self.dirty = true;
+                self.save()
+            }
+            None => false,
+        }
+    }
+
+    fn restore_sav(&mut self, sav: &[u8]) {
+        if let Some(zero) = rtc_footer_zero(sav) {
+            self.zero = zero;
         }
-        save_bytes(&self.save_path, &self.zero.to_be_bytes());
     }
 }
 
@@ -302,6 +404,7 @@ struct Mbc3 {
     ram_bank: usize,
     ram_enable: bool,
     rtc: RealTimeClock,
+    dirty: bool,
     save_path: PathBuf,
 }
 
@@ -314,6 +417,7 @@ impl Mbc3 {
             ram_bank: 0,
             ram_enable: false,
             rtc: RealTimeClock::power_up(rtc.as_ref().to_path_buf()),
+            dirty: false,
             save_path: sav.as_ref().to_path_buf(),
         }
     }
@@ -329,13 +433,13 @@ impl Memory for Mbc3 {
                 //     n = 0x01;
                 // }
                 let i = self.rom_bank * 0x4000 + a as usize - 0x4000;
-                self.rom[i]
+                self.rom[i & (self.rom.len() - 1)]
             }
             0xa000..=0xbfff => {
                 if self.ram_enable {
                     if self.ram_bank <= 0x03 {
                         let i = self.ram_bank * 0x2000 + a as usize - 0xa000;
-                        self.ram[i]
+                        ram_get(&self.ram, i)
                     } else {
                         self.rtc.get(self.ram_bank as u16)
                     }
@@ -355,7 +459,7 @@ impl Memory for Mbc3 {
                 }
                 if self.ram_bank <= 0x03 {
                     let i = self.ram_bank * 0x2000 + a as usize - 0xa000;
-                    self.ram[i] = b;
+                    self.dirty |= ram_set(&mut self.ram, i, b);
                 } else {
                     self.rtc.set(self.ram_bank as u16, b);
                 }
@@ -377,12 +481,39 @@ impl Memory for Mbc3 {
 }
 
 impl Stable for Mbc3 {
-    fn save(&self) {
-        self.rtc.save();
-        if self.save_path.to_str().unwrap().is_empty() {
-            return;
+    fn save(&mut self) -> bool {
+        let rtc_saved = self.rtc.save();
+        save_ram(&self.save_path, &self.ram, &mut self.dirty) && rtc_saved
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty || self.rtc.is_dirty()
+    }
+
+    fn export_sav(&self) -> Vec<u8> {
+        let mut sav = export_ram(&self.save_path, &self.ram);
+        sav.extend(self.rtc.export_sav());
+        sav
+    }
+
+    fn import_sav(&mut self, sav: &[u8]) -> bool {
+        match import_ram(&self.save_path, &mut self.ram, sav, &mut self.dirty) {
+            Some(footer) => {
+                if !footer.is_empty() {
+                    self.rtc.import_sav(footer);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn restore_sav(&mut self, sav: &[u8]) {
+        if let Some(footer) = restore_ram(&self.save_path, &mut self.ram, sav) {
+            if !footer.is_empty() {
+                self.rtc.restore_sav(footer);
+            }
         }
-        save_bytes(&self.save_path, &self.ram);
     }
 }
 
@@ -392,6 +523,9 @@ struct Mbc5 {
     rom_bank: usize,
     ram_bank: usize,
     ram_enable: bool,
+    // 震动卡带的马达状态，None表示卡带没有马达
+    motor: Option<bool>,
+    dirty: bool,
     save_path: PathBuf,
 }
 
@@ -403,9 +537,17 @@ impl Mbc5 {
             rom_bank: 1,
             ram_bank: 0,
             ram_enable: false,
+            motor: None,
+            dirty: false,
             save_path: PathBuf::from(sav.as_ref()),
         }
     }
+
+    // 带有震动马达的卡带
+    fn with_motor(mut self) -> Self {
+        self.motor = Some(false);
+        self
+    }
 }
 
 impl Memory for Mbc5 {
@@ -414,12 +556,12 @@ impl Memory for Mbc5 {
             0x0000..=0x3fff => self.rom[a as usize],
             0x4000..=0x7fff => {
                 let i = self.rom_bank * 0x4000 + a as usize - 0x4000;
-                self.rom[i]
+                self.rom[i & (self.rom.len() - 1)]
             }
             0xa000..=0xbfff => {
                 if self.ram_enable {
                     let i = self.ram_bank * 0x2000 + a as usize - 0xa000;
-                    self.ram[i]
+                    ram_get(&self.ram, i)
                 } else {
                     0x00
                 }
@@ -433,7 +575,7 @@ impl Memory for Mbc5 {
             0xa000..=0xbfff => {
                 if self.ram_enable {
                     let i = self.ram_bank * 0x2000 + a as usize - 0xa000;
-                    self.ram[i] = b;
+                    self.dirty |= ram_set(&mut self.ram, i, b);
                 }
             }
             0x0000..=0x1fff => self.ram_enable = (b & 0x0f) == 0x0a,
@@ -441,38 +583,46 @@ impl Memory for Mbc5 {
             0x3000..=0x3fff => {
                 self.rom_bank = (self.rom_bank & 0x00ff) | ((b as usize & 0x01) << 8)
             }
-            0x4000..=0x5fff => self.ram_bank = b as usize & 0x0f,
+            0x4000..=0x5fff => match &mut self.motor {
+                // 震动卡带用bit 3控制马达，只剩下bit 0-2选择ram bank
+                Some(motor) => {
+                    *motor = b & 0x08 != 0;
+                    self.ram_bank = b as usize & 0x07;
+                }
+                None => self.ram_bank = b as usize & 0x0f,
+            },
             _ => {}
         }
     }
 }
 
 impl Stable for Mbc5 {
-    fn save(&self) {
-        if self.save_path.to_str().unwrap().is_empty() {
-            return;
-        }
-        save_bytes(&self.save_path, &self.ram);
+    fn save(&mut self) -> bool {
+        save_ram(&self.save_path, &self.ram, &mut self.dirty)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn export_sav(&self) -> Vec<u8> {
+        export_ram(&self.save_path, &self.ram)
+    }
+
+    fn import_sav(&mut self, sav: &[u8]) -> bool {
+        import_ram(&self.save_path, &mut self.ram, sav, &mut self.dirty).is_some()
+    }
+
+    fn restore_sav(&mut self, sav: &[u8]) {
+        restore_ram(&self.save_path, &mut self.ram, sav);
     }
 }
 
 pub trait Cartridge: Memory + Stable + Send {
-    // 获取卡带标题
+    // 获取卡带标题，卡带头部总是映射在bank 0中
     fn title(&self) -> String {
-        let mut buf = String::new();
-        let ic = 0x0134;
-        let oc = if self.get(0x0143) == 0x80 {
-            0x013e
-        } else {
-            0x0143
-        };
-        for i in ic..oc {
-            match self.get(i) {
-                0 => break,
-                v => buf.push(v as char),
-            }
-        }
-        buf
+        let rom: Vec<u8> = (0x0000..0x0150).map(|a| self.get(a)).collect();
+        header::title(&rom)
     }
 
     fn term(&self) -> Term {
@@ -482,20 +632,73 @@ pub trait Cartridge: Memory + Stable + Send {
             Term::GB
         }
     }
+
+    // 连接摄像头的图像来源，只有Pocket Camera卡带需要
+    fn attach_camera(&mut self, _source: CameraSource) {}
+
+    // 使用模拟时间驱动卡带的时钟（MBC3 RTC），start为起始的Unix时间，录像回放时需要确定的结果
+    fn use_emulated_clock(&mut self, _start: u64) {}
+
+    // 模拟时间经过了cycles个时钟周期
+    fn tick(&mut self, _cycles: u32) {}
+
+    // 震动马达是否在转动
+    fn rumble(&self) -> bool {
+        false
+    }
 }
 
 // 初始化卡带
-pub fn power_up<T: AsRef<Path>>(path: T, save_path: T) -> Box<dyn Cartridge> {
-    let mut f = File::open(path.as_ref()).unwrap();
-    let mut rom = vec![];
-    f.read_to_end(&mut rom).unwrap();
+pub fn power_up(
+    mut rom: Vec<u8>,
+    save_path: impl AsRef<Path>,
+    options: &LoadOptions,
+) -> Box<dyn Cartridge> {
     if rom.len() < 0x150 {
         panic!("Missing required information area which located at 0100-014F")
     }
-    let rom_max = rom_size(rom[0x0148]);
-    if rom.len() > rom_max {
-        panic!("Rom size more than: {}", rom_max)
+    let check = |ok: bool, msg: String| {
+        if ok {
+            return;
+        }
+        if !options.lenient {
+            panic!("{}", msg)
+        }
+        log::warn!("{}", msg);
+    };
+    check(
+        rom[0x014d] == header::header_checksum(&rom),
+        String::from("Cartridge's header checksum is incorrect!"),
+    );
+    check(
+        header::logo_ok(&rom),
+        String::from("Nintendo logo is incorrect!"),
+    );
+    let rom_max = header::rom_size(rom[0x0148]);
+    check(
+        rom_max.is_some(),
+        format!("Unsupported rom size: 0x{:02x}", rom[0x0148]),
+    );
+    let rom_max = rom_max.unwrap_or(rom.len());
+    check(
+        rom.len() <= rom_max,
+        format!("Rom size more than: {}", rom_max),
+    );
+    check(
+        header::ram_size(rom[0x0149]).is_some(),
+        format!("Unsupported ram size: 0x{:02x}", rom[0x0149]),
+    );
+    // Pad the rom to a power of two like a real rom chip, so bank numbers beyond the end can be
+    // masked into it when reading
+    let size = rom.len().max(0x8000).next_power_of_two();
+    if !rom.len().is_multiple_of(0x4000) || rom.len() < rom_max {
+        log::warn!(
+            "Rom size is {}, but the header declares {}",
+            rom.len(),
+            rom_max
+        );
     }
+    rom.resize(size, 0xff);
 
     // The path to file where save game ram data
     let ram_save_path = save_path.as_ref().join("ram");
@@ -522,11 +725,20 @@ pub fn power_up<T: AsRef<Path>>(path: T, save_path: T) -> Box<dyn Cartridge> {
             let ram = ram_read(ram_save_path.clone(), ram_max);
             Box::new(Mbc2::power_up(rom, ram, ram_save_path))
         }
-        0x0f => Box::new(Mbc3::power_up(rom, vec![], ram_save_path, rtc_save_path)),
-        0x10 => {
-            let ram_max = ram_size(rom[0x149]);
-            let ram = ram_read(ram_save_path.clone(), ram_max);
-            Box::new(Mbc3::power_up(rom, ram, ram_save_path, rtc_save_path))
+        0x0f | 0x10 => {
+            let ram_max = if rom[0x0147] == 0x0f {
+                0
+            } else {
+                ram_size(rom[0x149])
+            };
+            let sav = read_bytes(&ram_save_path).unwrap_or_default();
+            let (ram, footer) = parse_sav(&sav, ram_max);
+            let mut cart = Mbc3::power_up(rom, ram, ram_save_path, rtc_save_path);
+            if cart.rtc.dirty && !footer.is_empty() {
+                // There is no rtc file, the save may come from other emulators
+                cart.rtc.import_sav(footer);
+            }
+            Box::new(cart)
         }
         0x11 => Box::new(Mbc3::power_up(rom, vec![], "", "")),
         0x12 => {
@@ -548,142 +760,253 @@ pub fn power_up<T: AsRef<Path>>(path: T, save_path: T) -> Box<dyn Cartridge> {
             let ram = ram_read(ram_save_path.clone(), ram_max);
             Box::new(Mbc5::power_up(rom, ram, ram_save_path))
         }
+        0x1c => Box::new(Mbc5::power_up(rom, vec![], "").with_motor()),
+        0x1d => {
+            let ram_max = ram_size(rom[0x149]);
+            Box::new(Mbc5::power_up(rom, vec![0; ram_max], "").with_motor())
+        }
+        0x1e => {
+            let ram_max = ram_size(rom[0x149]);
+            let ram = ram_read(ram_save_path.clone(), ram_max);
+            Box::new(Mbc5::power_up(rom, ram, ram_save_path).with_motor())
+        }
+        0xfc => {
+            let ram_max = ram_size(rom[0x149]);
+            let ram = ram_read(ram_save_path.clone(), ram_max);
+            Box::new(PocketCamera::power_up(rom, ram, ram_save_path))
+        }
         n => panic!("Unsupported cartridge type: {:#04x}", n),
     };
     println!("Cartridge title: {}", cart.title());
     println!("Cartridge type: {}", mbc_info(cart.as_ref()));
     log::info!(
-        "Load cartridge {} from {}, type: {}",
+        "Load cartridge {}, type: {}",
         cart.title(),
-        path.as_ref().to_string_lossy(),
         mbc_info(cart.as_ref())
     );
-    ensure_header_checksum(cart.as_ref());
-    ensure_logo(cart.as_ref());
     cart
 }
 
 fn mbc_info(cart: &dyn Cartridge) -> String {
     let ty = cart.get(0x147);
-    String::from(match ty {
-        0x00 => "ROM ONLY",
-        0x01 => "MBC1",
-        0x02 => "MBC1+RAM",
-        0x03 => "MBC1+RAM+BATTERY",
-        0x05 => "MBC2",
-        0x06 => "MBC2+BATTERY",
-        0x08 => "ROM+RAM",
-        0x09 => "ROM+RAM+BATTERY",
-        0x0b => "MMM01",
-        0x0c => "MMM01+RAM",
-        0x0d => "MMM01+RAM+BATTERY",
-        0x0f => "MBC3+TIMER+BATTERY",
-        0x10 => "MBC3+TIMER+RAM+BATTERY",
-        0x11 => "MBC3",
-        0x12 => "MBC3+RAM",
-        0x13 => "MBC3+RAM+BATTERY",
-        0x15 => "MBC4",
-        0x16 => "MBC4+RAM",
-        0x17 => "MBC4+RAM+BATTERY",
-        0x19 => "MBC5",
-        0x1a => "MBC5+RAM",
-        0x1b => "MBC5+RAM+BATTERY",
-        0x1c => "MBC5+RUMBLE",
-        0x1d => "MBC5+RUMBLE+RAM",
-        0x1e => "MBC5+RUMBLE+RAM+BATTERY",
-        0xfc => "POCKET CAMERA",
-        0xfd => "BANDAI TAMA5",
-        0xfe => "HuC3",
-        0x1f => "HuC1+RAM+BATTERY",
-        n => panic!("Unsupported cartridge type: 0x{:02x}", n),
-    })
-}
-
-const NINTENDO_LOGO: [u8; 48] = [
-    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
-    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
-    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
-];
-
-// 验证任天堂logo
-fn ensure_logo(cart: &dyn Cartridge) {
-    for i in 0..48 {
-        if cart.get(0x0104 + i) != NINTENDO_LOGO[i as usize] {
-            panic!("Nintendo logo is incorrect!")
-        }
-    }
-}
-
-// 验证标题校验和
-fn ensure_header_checksum(cart: &dyn Cartridge) {
-    let mut v: u8 = 0;
-    for i in 0x0134..0x014d {
-        v = v.wrapping_sub(cart.get(i)).wrapping_sub(1);
-    }
-
-    if cart.get(0x014d) != v {
-        panic!("Cartridge's header checksum is incorrect!")
-    }
-}
-
-// 获取卡带中rom的容量
-fn rom_size(b: u8) -> usize {
-    let bank = 16384;
-    match b {
-        0x00 => bank * 2,
-        0x01 => bank * 4,
-        0x02 => bank * 8,
-        0x03 => bank * 16,
-        0x04 => bank * 32,
-        0x05 => bank * 64,
-        0x06 => bank * 128,
-        0x07 => bank * 256,
-        0x08 => bank * 512,
-        0x52 => bank * 72,
-        0x53 => bank * 80,
-        0x54 => bank * 96,
-        n => panic!("Unsupported rom size: 0x{:02x}", n),
-    }
-}
-
-// 获取卡带中ram的容量
-fn ram_size(b: u8) -> usize {
-    match b {
-        0x00 => 0,
-        0x01 => 1024 * 2,
-        0x02 => 1024 * 8,
-        0x03 => 1024 * 32,
-        0x04 => 1024 * 128,
-        0x05 => 1024 * 64,
-        n => panic!("Unsupported ram size: 0x{:02x}", n),
+    match header::mbc_name(ty) {
+        Some(name) => String::from(name),
+        None => panic!("Unsupported cartridge type: 0x{:02x}", ty),
     }
 }
 
+// 获取卡带中ram的容量，未知的容量按8KB处理
+fn ram_size(b: u8) -> usize {
+    header::ram_size(b).unwrap_or(0x2000)
+}
+
+/// Read battery backed ram from local file, the data is padded or truncated to [size] bytes
 fn ram_read(sav: impl AsRef<Path>, size: usize) -> Vec<u8> {
-    match File::open(sav) {
-        Ok(mut f) => {
-            let mut ram = vec![];
-            f.read_to_end(&mut ram).unwrap();
-            ram
+    match read_bytes(sav.as_ref()) {
+        Some(v) => parse_sav(&v, size).0,
+        None => vec![0; size],
+    }
+}
+
+/// Length of the RTC footer appended to MBC3 .sav files by other emulators (VBA-M, BGB...)
+const RTC_FOOTER_LEN: usize = 48;
+/// Some emulators write the timestamp as 32 bits, so the footer is 4 bytes shorter
+const RTC_FOOTER_LEN_32: usize = 44;
+
+/// Split a .sav file into ram of [size] bytes and the RTC footer (empty if there is none). Ram
+/// is padded with 0x00 or truncated if the file size is unexpected
+fn parse_sav(sav: &[u8], size: usize) -> (Vec<u8>, &[u8]) {
+    let (data, footer) = match sav.len().checked_sub(size) {
+        Some(RTC_FOOTER_LEN) | Some(RTC_FOOTER_LEN_32) => sav.split_at(size),
+        _ => (sav, &sav[sav.len()..]),
+    };
+    if data.len() != size {
+        log::warn!(
+            "Save size {} doesn't match ram size {}, resize it",
+            data.len(),
+            size
+        );
+    }
+    let mut ram = data.to_vec();
+    ram.resize(size, 0x00);
+    (ram, footer)
+}
+
+/// Export ram as .sav data, it's empty if the cartridge has no battery
+pub fn export_ram(path: &Path, ram: &[u8]) -> Vec<u8> {
+    if path.as_os_str().is_empty() {
+        return vec![];
+    }
+    ram.to_vec()
+}
+
+/// Replace [ram] with a .sav file and save it, return the RTC footer of the .sav file, or None
+/// if the cartridge has no battery
+pub fn import_ram<'a>(
+    path: &Path,
+    ram: &mut Vec<u8>,
+    sav: &'a [u8],
+    dirty: &mut bool,
+) -> Option<&'a [u8]> {
+    let footer = restore_ram(path, ram, sav)?;
+    *dirty = true;
+    save_ram(path, ram, dirty);
+    Some(footer)
+}
+
+/// Replace [ram] with a .sav file without saving it, return the RTC footer of the .sav file, or
+/// None if the cartridge has no battery
+pub fn restore_ram<'a>(path: &Path, ram: &mut Vec<u8>, sav: &'a [u8]) -> Option<&'a [u8]> {
+    if path.as_os_str().is_empty() {
+        return None;
+    }
+    let (data, footer) = parse_sav(sav, ram.len());
+    *ram = data;
+    Some(footer)
+}
+
+/// Read external ram, addresses beyond the ram size wrap around like the hardware does
+fn ram_get(ram: &[u8], i: usize) -> u8 {
+    if ram.is_empty() {
+        return 0xff;
+    }
+    ram[i % ram.len()]
+}
+
+/// Write external ram, return true if the value changed
+fn ram_set(ram: &mut [u8], i: usize, v: u8) -> bool {
+    if ram.is_empty() {
+        return false;
+    }
+    let i = i % ram.len();
+    let changed = ram[i] != v;
+    ram[i] = v;
+    changed
+}
+
+/// Seconds since unix epoch
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Build the 48 bytes RTC footer, [elapsed] is the clock time in seconds and [now] is the unix
+/// timestamp when it's saved. The footer is ten little endian u32 (seconds, minutes, hours, days
+/// low, days high, then the latched copy of them) followed by a little endian u64 timestamp
+fn rtc_footer(elapsed: u64, now: u64) -> Vec<u8> {
+    let days = elapsed / 86400;
+    let mut dh = ((days >> 8) & 0x01) as u32;
+    if days > 0x1ff {
+        dh |= 0x80;
+    }
+    let regs = [
+        (elapsed % 60) as u32,
+        (elapsed / 60 % 60) as u32,
+        (elapsed / 3600 % 24) as u32,
+        (days & 0xff) as u32,
+        dh,
+    ];
+    let mut footer = Vec::with_capacity(RTC_FOOTER_LEN);
+    for _ in 0..2 {
+        for r in regs {
+            footer.extend(r.to_le_bytes());
         }
-        Err(_) => vec![0; size],
     }
+    footer.extend(now.to_le_bytes());
+    footer
+}
+
+/// Get the zero time of RTC from a footer built by [rtc_footer]
+fn rtc_footer_zero(footer: &[u8]) -> Option<u64> {
+    if footer.len() != RTC_FOOTER_LEN && footer.len() != RTC_FOOTER_LEN_32 {
+        return None;
+    }
+    let word = |i: usize| {
+        u64::from(u32::from_le_bytes(
+            footer[i * 4..i * 4 + 4].try_into().unwrap(),
+        ))
+    };
+    let days = (word(3) & 0xff) | ((word(4) & 0x01) << 8);
+    let elapsed = word(0) + word(1) * 60 + word(2) * 3600 + days * 86400;
+    let saved_at = if footer.len() == RTC_FOOTER_LEN {
+        u64::from_le_bytes(footer[40..48].try_into().unwrap())
+    } else {
+        word(10)
+    };
+    Some(saved_at.saturating_sub(elapsed))
+}
+
+/// Read bytes from local file, fall back to the backup file if it's missing or unreadable. An
+/// empty path means the cartridge has no battery
+fn read_bytes(path: &Path) -> Option<Vec<u8>> {
+    if path.as_os_str().is_empty() {
+        return None;
+    }
+    match fs::read(path) {
+        Ok(v) => Some(v),
+        Err(_) => {
+            let bak = with_suffix(path, "bak");
+            let v = fs::read(&bak).ok()?;
+            log::warn!("Restore {} from backup", path.to_string_lossy());
+            Some(v)
+        }
+    }
+}
+
+/// Save battery backed [bytes] to [path] if they are [dirty], an empty path means the cartridge
+/// has no battery
+pub fn save_ram(path: &Path, bytes: &[u8], dirty: &mut bool) -> bool {
+    if !*dirty || path.as_os_str().is_empty() {
+        return true;
+    }
+    let saved = save_bytes(path, bytes);
+    *dirty = !saved;
+    saved
+}
+
+/// Append [suffix] to the file name of [path], e.g. ram -> ram.bak
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
 }
 
 /// Save bytes to local file
-fn save_bytes(path: &PathBuf, bytes: &[u8]) {
+///
+/// Bytes are written to a temporary file first, then the old file is kept as a backup and the
+/// temporary file is renamed to [path], so a crash in the middle never corrupts the save
+fn save_bytes(path: &Path, bytes: &[u8]) -> bool {
     log::info!("Save bytes to {}", path.to_string_lossy());
     if let Some(p) = path.parent() {
         // Create parent directory if it doesn't exist
         if let Err(err) = create_dir_all(p) {
             log::error!("Create dir {} error: {}", p.to_string_lossy(), err);
-            return;
+            return false;
         }
     }
-    let res = File::create(path.clone()).and_then(|mut f| f.write_all(bytes));
+    let tmp = with_suffix(path, "tmp");
+    let res = File::create(&tmp).and_then(|mut f| {
+        f.write_all(bytes)?;
+        f.sync_all()
+    });
     if let Err(err) = res {
-        log::error!("Save bytes error: {}", err)
+        log::error!("Save bytes error: {}", err);
+        return false;
+    }
+    // Rotate the previous save to backup
+    if path.exists() {
+        if let Err(err) = fs::rename(path, with_suffix(path, "bak")) {
+            log::warn!("Backup {} error: {}", path.to_string_lossy(), err);
+        }
     }
+    if let Err(err) = fs::rename(&tmp, path) {
+        log::error!("Save bytes error: {}", err);
+        return false;
+    }
+    true
 }
 
 impl Cartridge for RomOnly {}
@@ -692,6 +1015,103 @@ impl Cartridge for Mbc1 {}
 
 impl Cartridge for Mbc2 {}
 
-impl Cartridge for Mbc3 {}
+impl Cartridge for Mbc3 {
+    fn use_emulated_clock(&mut self, start: u64) {
+        self.rtc.emulated = Some((start, 0));
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.rtc.next(cycles);
+    }
+}
 
-impl Cartridge for Mbc5 {}
+impl Cartridge for Mbc5 {
+    fn rumble(&self) -> bool {
+        self.motor == Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A rom of [len] bytes with an MBC1 header declaring 4 banks, and the bank number at the
+    /// start of each bank. The logo and header checksum are wrong
+    fn test_rom(len: usize) -> Vec<u8> {
+        let mut rom = vec![0; len];
+        for (i, bank) in rom.chunks_mut(0x4000).enumerate() {
+            bank[0] = i as u8;
+        }
+        rom[0x0147] = 0x01;
+        rom[0x0148] = 0x01;
+        rom
+    }
+
+    fn lenient() -> LoadOptions {
+        LoadOptions {
+            lenient: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_strict_power_up() {
+        power_up(test_rom(0x10000), "", &LoadOptions::default());
+    }
+
+    #[test]
+    fn test_lenient_short_rom() {
+        // Half a bank is missing, it's padded with 0xff
+        let mut cart = power_up(test_rom(0x6000), "", &lenient());
+        cart.set(0x2000, 0x01);
+        assert_eq!(cart.get(0x4000), 0x01);
+        assert_eq!(cart.get(0x6000), 0xff);
+        // Bank 3 is mirrored to bank 1
+        cart.set(0x2000, 0x03);
+        assert_eq!(cart.get(0x4000), 0x01);
+    }
+
+    #[test]
+    fn test_lenient_odd_banks() {
+        // 3 banks are padded to 4, bank 6 is masked to bank 2
+        let mut cart = power_up(test_rom(0xc000), "", &lenient());
+        cart.set(0x2000, 0x06);
+        assert_eq!(cart.get(0x4000), 0x02);
+        cart.set(0x2000, 0x07);
+        assert_eq!(cart.get(0x4000), 0xff);
+    }
+
+    #[test]
+    fn test_parse_sav() {
+        // Truncated save is padded
+        let (ram, footer) = parse_sav(&[1, 2, 3], 8);
+        assert_eq!(ram, vec![1, 2, 3, 0, 0, 0, 0, 0]);
+        assert!(footer.is_empty());
+
+        // Oversized save is truncated
+        let (ram, _) = parse_sav(&[1; 12], 8);
+        assert_eq!(ram.len(), 8);
+
+        // RTC footer is split from ram
+        let mut sav = vec![7; 8];
+        sav.extend(rtc_footer(90061, 1_000_000));
+        let (ram, footer) = parse_sav(&sav, 8);
+        assert_eq!(ram, vec![7; 8]);
+        assert_eq!(footer.len(), RTC_FOOTER_LEN);
+    }
+
+    #[test]
+    fn test_rtc_footer() {
+        // 1 day 1 hour 1 minute 1 second
+        let footer = rtc_footer(90061, 1_000_000);
+        assert_eq!(&footer[0..4], &1u32.to_le_bytes());
+        assert_eq!(&footer[12..16], &1u32.to_le_bytes());
+        assert_eq!(rtc_footer_zero(&footer), Some(1_000_000 - 90061));
+        // 32 bits timestamp
+        let mut short = footer[..40].to_vec();
+        short.extend(1_000_000u32.to_le_bytes());
+        assert_eq!(rtc_footer_zero(&short), Some(1_000_000 - 90061));
+        assert_eq!(rtc_footer_zero(&footer[..10]), None);
+    }
+}
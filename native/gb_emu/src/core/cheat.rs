@@ -0,0 +1,352 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::memory::Memory;
+
+/// What a cheat code does
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Effect {
+    /// Game Genie: replace the rom byte at [address] with [value], only when the original byte
+    /// equals [compare] if it's present
+    Genie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// GameShark: write [value] to ram at [address] every frame, [bank] is the ram bank type
+    Shark { bank: u8, address: u16, value: u8 },
+}
+
+/// Errors happened when parsing a cheat code
+#[derive(Debug)]
+pub enum CheatError {
+    /// Neither a Game Genie code (ABC-DEF or ABC-DEF-GHI) nor a GameShark code (ABCDEFGH)
+    InvalidCode(String),
+    /// Game Genie codes can only patch rom (0000-7FFF)
+    NotRom(String),
+    /// GameShark codes can only write external or work ram (A000-DFFF), writing elsewhere every
+    /// frame would switch banks or hit unwritable memory
+    NotRam(String),
+    /// Cheats belong to a game, they can't be edited before one is running
+    NotRunning,
+}
+
+impl Display for CheatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatError::InvalidCode(code) => write!(f, "Invalid cheat code: {}", code),
+            CheatError::NotRom(code) => write!(f, "Game Genie code {} doesn't patch rom", code),
+            CheatError::NotRam(code) => write!(f, "GameShark code {} doesn't write ram", code),
+            CheatError::NotRunning => write!(f, "No game is running"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Cheat {
+    /// Normalized code, upper case hex digits with dashes for Game Genie codes
+    pub code: String,
+    pub name: String,
+    pub enabled: bool,
+    effect: Effect,
+}
+
+impl Cheat {
+    pub fn parse(code: &str, name: &str) -> Result<Self, CheatError> {
+        let code = code.trim().to_uppercase();
+        let digits: String = code.chars().filter(|c| *c != '-').collect();
+        let invalid = || CheatError::InvalidCode(code.clone());
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let hex = |r: std::ops::Range<usize>| u32::from_str_radix(&digits[r], 16).unwrap();
+        let effect = match digits.len() {
+            // Game Genie, ABC-DEF or ABC-DEF-GHI
+            6 | 9 => {
+                // 地址为FCDE，其中F与0xF进行了异或
+                let address = ((hex(5..6) ^ 0x0f) << 12 | hex(2..5)) as u16;
+                if address > 0x7fff {
+                    return Err(CheatError::NotRom(code));
+                }
+                // 比较值为GI，循环右移2位后与0xBA异或，H没有使用
+                let compare = (digits.len() == 9)
+                    .then(|| ((hex(6..7) << 4 | hex(8..9)) as u8).rotate_right(2) ^ 0xba);
+                Effect::Genie {
+                    address,
+                    value: hex(0..2) as u8,
+                    compare,
+                }
+            }
+            // GameShark, ABCDEFGH: AB bank, CD value, GHEF address
+            8 if !code.contains('-') => {
+                let address = (hex(6..8) << 8 | hex(4..6)) as u16;
+                if !(0xa000..=0xdfff).contains(&address) {
+                    return Err(CheatError::NotRam(code));
+                }
+                Effect::Shark {
+                    bank: hex(0..2) as u8,
+                    value: hex(2..4) as u8,
+                    address,
+                }
+            }
+            _ => return Err(invalid()),
+        };
+        let code = match effect {
+            Effect::Genie { .. } => digits
+                .as_bytes()
+                .chunks(3)
+                .map(|c| String::from_utf8_lossy(c).into_owned())
+                .collect::<Vec<_>>()
+                .join("-"),
+            Effect::Shark { .. } => digits,
+        };
+        Ok(Self {
+            code,
+            name: name.trim().replace(['\t', '\r', '\n'], " "),
+            enabled: true,
+            effect,
+        })
+    }
+}
+
+/// All cheats of a rom, they are saved in a text file, one cheat each line:
+/// `<code>\t<1 if enabled else 0>\t<name>`
+#[derive(Clone, Default)]
+pub struct Cheats {
+    list: Vec<Cheat>,
+    /// Enabled Game Genie codes, checked on every rom read
+    genie: Vec<Effect>,
+    save_path: PathBuf,
+}
+
+impl Cheats {
+    /// Load cheats from [path], it's empty if the file doesn't exist
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let text = fs::read_to_string(path.as_ref()).unwrap_or_default();
+        Self {
+            save_path: path.as_ref().to_path_buf(),
+            ..Self::parse(&text)
+        }
+    }
+
+    /// Parse cheats in the cheat file format, they aren't saved anywhere
+    pub fn parse(text: &str) -> Self {
+        let mut cheats = Self::default();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let mut fields = line.splitn(3, '\t');
+            let code = fields.next().unwrap_or_default();
+            let enabled = fields.next() != Some("0");
+            let name = fields.next().unwrap_or_default();
+            match Cheat::parse(code, name) {
+                Ok(mut cheat) => {
+                    cheat.enabled = enabled;
+                    cheats.list.push(cheat);
+                }
+                Err(err) => log::warn!("Skip cheat: {}", err),
+            }
+        }
+        cheats.update();
+        cheats
+    }
+
+    /// Write cheats to the cheat file
+    pub fn save(&self) -> bool {
+        if self.save_path.as_os_str().is_empty() {
+            return false;
+        }
+        let res = fs::write(&self.save_path, self.to_string());
+        if let Err(err) = &res {
+            log::error!(
+                "Save cheats to {} failed: {}",
+                self.save_path.to_string_lossy(),
+                err
+            );
+        }
+        res.is_ok()
+    }
+
+    /// Add a cheat, or replace the one with the same code
+    pub fn add(&mut self, code: &str, name: &str) -> Result<(), CheatError> {
+        let cheat = Cheat::parse(code, name)?;
+        match self.list.iter_mut().find(|c| c.code == cheat.code) {
+            Some(c) => *c = cheat,
+            None => self.list.push(cheat),
+        }
+        self.update();
+        Ok(())
+    }
+
+    pub fn remove(&mut self, code: &str) -> bool {
+        let code = normalize(code);
+        let len = self.list.len();
+        self.list.retain(|c| c.code != code);
+        self.update();
+        self.list.len() != len
+    }
+
+    pub fn set_enabled(&mut self, code: &str, enabled: bool) -> bool {
+        let code = normalize(code);
+        let found = match self.list.iter_mut().find(|c| c.code == code) {
+            Some(c) => {
+                c.enabled = enabled;
+                true
+            }
+            None => false,
+        };
+        self.update();
+        found
+    }
+
+    /// Add cheats of [other] which aren't in this list, return whether any is added
+    pub fn merge(&mut self, other: &Cheats) -> bool {
+        let len = self.list.len();
+        for cheat in &other.list {
+            if !self.list.iter().any(|c| c.code == cheat.code) {
+                self.list.push(cheat.clone());
+            }
+        }
+        self.update();
+        self.list.len() != len
+    }
+
+    /// Rebuild the enabled Game Genie codes
+    fn update(&mut self) {
+        self.genie = self
+            .list
+            .iter()
+            .filter(|c| c.enabled && matches!(c.effect, Effect::Genie { .. }))
+            .map(|c| c.effect)
+            .collect();
+    }
+
+    /// Apply Game Genie codes to byte [v] read from rom address [a]
+    pub fn patch_rom(&self, a: u16, v: u8) -> u8 {
+        for effect in &self.genie {
+            if let Effect::Genie {
+                address,
+                value,
+                compare,
+            } = *effect
+            {
+                if address == a && compare.is_none_or(|c| c == v) {
+                    return value;
+                }
+            }
+        }
+        v
+    }
+
+    /// Write GameShark codes to ram, it should be called once per frame
+    pub fn apply_shark(&self, mem: &mut impl Memory) {
+        for cheat in self.list.iter().filter(|c| c.enabled) {
+            if let Effect::Shark {
+                bank,
+                address,
+                value,
+            } = cheat.effect
+            {
+                match bank {
+                    // 9x: WRAM bank x of CGB, switch to the bank while writing
+                    0x90..=0x97 if (0xd000..=0xdfff).contains(&address) => {
+                        let svbk = mem.get(0xff70);
+                        mem.set(0xff70, bank & 0x07);
+                        mem.set(address, value);
+                        mem.set(0xff70, svbk);
+                    }
+                    // 01, 8x: write to the current bank
+                    _ => mem.set(address, value),
+                }
+            }
+        }
+    }
+}
+
+impl Display for Cheats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for c in &self.list {
+            writeln!(f, "{}\t{}\t{}", c.code, c.enabled as u8, c.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Normalize [code] the same way as [Cheat::parse]
+fn normalize(code: &str) -> String {
+    match Cheat::parse(code, "") {
+        Ok(cheat) => cheat.code,
+        Err(_) => code.trim().to_uppercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cheat = Cheat::parse("00a-17b-c49", "").unwrap();
+        assert_eq!(cheat.code, "00A-17B-C49");
+        assert_eq!(
+            cheat.effect,
+            Effect::Genie {
+                address: 0x4a17,
+                value: 0x00,
+                compare: Some(0xc8),
+            }
+        );
+
+        let cheat = Cheat::parse("010B3CD1", "").unwrap();
+        assert_eq!(
+            cheat.effect,
+            Effect::Shark {
+                bank: 0x01,
+                value: 0x0b,
+                address: 0xd13c,
+            }
+        );
+        assert!(Cheat::parse("01-0B3CD1", "").is_err());
+        // GameShark codes writing rom or io registers are rejected
+        assert!(matches!(
+            Cheat::parse("01010020", ""),
+            Err(CheatError::NotRam(_))
+        ));
+        assert!(matches!(
+            Cheat::parse("010100FF", ""),
+            Err(CheatError::NotRam(_))
+        ));
+        assert!(Cheat::parse("010100A0", "").is_ok());
+        assert!(Cheat::parse("XYZ-123", "").is_err());
+    }
+
+    #[test]
+    fn test_patch_rom() {
+        let mut cheats = Cheats::default();
+        cheats.add("3EA-17B-E6E", "").unwrap();
+        let Effect::Genie {
+            address, compare, ..
+        } = cheats.list[0].effect
+        else {
+            unreachable!()
+        };
+        let compare = compare.unwrap();
+        assert_eq!(cheats.patch_rom(address, compare), 0x3e);
+        assert_eq!(cheats.patch_rom(address, compare ^ 0x01), compare ^ 0x01);
+        cheats.set_enabled("3ea17be6e", false);
+        assert_eq!(cheats.patch_rom(address, compare), compare);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut cheats = Cheats::default();
+        cheats.add("010B3CD1", "disk").unwrap();
+        let mut memory = Cheats::default();
+        memory.add("010B3CD1", "memory").unwrap();
+        memory.add("00A-17B-C49", "").unwrap();
+        assert!(cheats.merge(&memory));
+        assert_eq!(cheats.list.len(), 2);
+        assert_eq!(cheats.list[0].name, "disk");
+        assert_eq!(cheats.genie.len(), 1);
+        assert!(!cheats.merge(&memory));
+    }
+}
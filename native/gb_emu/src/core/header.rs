@@ -0,0 +1,217 @@
+use std::os::raw::c_char;
+
+/// Length of the title buffer in [RomInfo], the longest title is 16 bytes plus a nul terminator
+pub const TITLE_LEN: usize = 17;
+/// Length of the cartridge type name buffer in [RomInfo]
+pub const TYPE_NAME_LEN: usize = 32;
+
+/// Cartridge header located at 0100-014F, parsed without powering up the cartridge
+#[repr(C)]
+pub struct RomInfo {
+    /// Nul terminated title, 0134-0143
+    pub title: [c_char; TITLE_LEN],
+    /// 0143, 0x80: supports CGB functions, 0xc0: works on CGB only
+    pub cgb_flag: u8,
+    /// 0146, 0x03: supports SGB functions
+    pub sgb_flag: u8,
+    /// 014B, 0x33 means the new licensee code is used instead
+    pub old_licensee: u8,
+    /// Nul terminated new licensee code, 0144-0145
+    pub new_licensee: [c_char; 3],
+    /// 0147
+    pub cart_type: u8,
+    /// Nul terminated name of the cartridge type, "UNKNOWN" if it's not a known type
+    pub cart_type_name: [c_char; TYPE_NAME_LEN],
+    /// Rom size in bytes according to 0148, 0 if the code is unknown
+    pub rom_size: u32,
+    /// Ram size in bytes according to 0149, 0 if the code is unknown
+    pub ram_size: u32,
+    /// 014C
+    pub version: u8,
+    /// 014D
+    pub header_checksum: u8,
+    /// 014E-014F, big endian
+    pub global_checksum: u16,
+    pub logo_ok: bool,
+    pub header_checksum_ok: bool,
+    pub global_checksum_ok: bool,
+}
+
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Parse the header of [rom], returns None if the rom is too small to contain one
+pub fn parse(rom: &[u8]) -> Option<RomInfo> {
+    if rom.len() < 0x150 {
+        return None;
+    }
+    let mut info = RomInfo {
+        title: [0; TITLE_LEN],
+        cgb_flag: rom[0x0143],
+        sgb_flag: rom[0x0146],
+        old_licensee: rom[0x014b],
+        new_licensee: [0; 3],
+        cart_type: rom[0x0147],
+        cart_type_name: [0; TYPE_NAME_LEN],
+        rom_size: rom_size(rom[0x0148]).unwrap_or(0) as u32,
+        ram_size: ram_size(rom[0x0149]).unwrap_or(0) as u32,
+        version: rom[0x014c],
+        header_checksum: rom[0x014d],
+        global_checksum: u16::from_be_bytes([rom[0x014e], rom[0x014f]]),
+        logo_ok: logo_ok(rom),
+        header_checksum_ok: header_checksum(rom) == rom[0x014d],
+        global_checksum_ok: false,
+    };
+    info.global_checksum_ok = global_checksum(rom) == info.global_checksum;
+    copy_str(&mut info.title, title(rom).as_bytes());
+    copy_str(&mut info.new_licensee, &rom[0x0144..0x0146]);
+    copy_str(
+        &mut info.cart_type_name,
+        mbc_name(info.cart_type).unwrap_or("UNKNOWN").as_bytes(),
+    );
+    Some(info)
+}
+
+/// Copy [src] into [dst] as a nul terminated string, truncate if it's too long
+fn copy_str(dst: &mut [c_char], src: &[u8]) {
+    let n = src.len().min(dst.len() - 1);
+    for (d, s) in dst.iter_mut().zip(&src[..n]) {
+        *d = *s as c_char;
+    }
+    dst[n] = 0;
+}
+
+/// 获取卡带标题
+pub fn title(rom: &[u8]) -> String {
+    // 新的卡带标题只有11个字节，后面是厂商代码
+    let end = if rom[0x0143] == 0x80 { 0x013e } else { 0x0143 };
+    rom[0x0134..end]
+        .iter()
+        .take_while(|v| **v != 0)
+        .map(|v| *v as char)
+        .collect()
+}
+
+/// 验证任天堂logo
+pub fn logo_ok(rom: &[u8]) -> bool {
+    rom[0x0104..0x0134] == NINTENDO_LOGO
+}
+
+/// 计算标题校验和，应该和014D中的值相等
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[0x0134..0x014d]
+        .iter()
+        .fold(0u8, |v, b| v.wrapping_sub(*b).wrapping_sub(1))
+}
+
+/// 计算整个rom的校验和，不包括014E-014F本身，应该和014E-014F中的值相等
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(i, _)| *i != 0x014e && *i != 0x014f)
+        .fold(0u16, |v, (_, b)| v.wrapping_add(u16::from(*b)))
+}
+
+/// 获取卡带中rom的容量
+pub fn rom_size(b: u8) -> Option<usize> {
+    let bank = 16384;
+    Some(match b {
+        0x00 => bank * 2,
+        0x01 => bank * 4,
+        0x02 => bank * 8,
+        0x03 => bank * 16,
+        0x04 => bank * 32,
+        0x05 => bank * 64,
+        0x06 => bank * 128,
+        0x07 => bank * 256,
+        0x08 => bank * 512,
+        0x52 => bank * 72,
+        0x53 => bank * 80,
+        0x54 => bank * 96,
+        _ => return None,
+    })
+}
+
+/// 获取卡带中ram的容量
+pub fn ram_size(b: u8) -> Option<usize> {
+    Some(match b {
+        0x00 => 0,
+        0x01 => 1024 * 2,
+        0x02 => 1024 * 8,
+        0x03 => 1024 * 32,
+        0x04 => 1024 * 128,
+        0x05 => 1024 * 64,
+        _ => return None,
+    })
+}
+
+/// 卡带类型的名称
+pub fn mbc_name(ty: u8) -> Option<&'static str> {
+    Some(match ty {
+        0x00 => "ROM ONLY",
+        0x01 => "MBC1",
+        0x02 => "MBC1+RAM",
+        0x03 => "MBC1+RAM+BATTERY",
+        0x05 => "MBC2",
+        0x06 => "MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0b => "MMM01",
+        0x0c => "MMM01+RAM",
+        0x0d => "MMM01+RAM+BATTERY",
+        0x0f => "MBC3+TIMER+BATTERY",
+        0x10 => "MBC3+TIMER+RAM+BATTERY",
+        0x11 => "MBC3",
+        0x12 => "MBC3+RAM",
+        0x13 => "MBC3+RAM+BATTERY",
+        0x15 => "MBC4",
+        0x16 => "MBC4+RAM",
+        0x17 => "MBC4+RAM+BATTERY",
+        0x19 => "MBC5",
+        0x1a => "MBC5+RAM",
+        0x1b => "MBC5+RAM+BATTERY",
+        0x1c => "MBC5+RUMBLE",
+        0x1d => "MBC5+RUMBLE+RAM",
+        0x1e => "MBC5+RUMBLE+RAM+BATTERY",
+        0xfc => "POCKET CAMERA",
+        0xfd => "BANDAI TAMA5",
+        0xfe => "HuC3",
+        0x1f => "HuC1+RAM+BATTERY",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom[0x0147] = 0x13;
+        rom[0x0149] = 0x03;
+        rom[0x014d] = header_checksum(&rom);
+        let [hi, lo] = global_checksum(&rom).to_be_bytes();
+        rom[0x014e] = hi;
+        rom[0x014f] = lo;
+
+        let info = parse(&rom).unwrap();
+        assert_eq!(
+            info.title[..5],
+            [b'T', b'E', b'S', b'T', 0].map(|v| v as c_char)
+        );
+        assert_eq!(info.rom_size, 0x8000);
+        assert_eq!(info.ram_size, 0x8000);
+        assert!(info.logo_ok && info.header_checksum_ok && info.global_checksum_ok);
+
+        rom[0x0134] = b'X';
+        let info = parse(&rom).unwrap();
+        assert!(!info.header_checksum_ok && !info.global_checksum_ok);
+        assert!(parse(&rom[..0x14f]).is_none());
+    }
+}
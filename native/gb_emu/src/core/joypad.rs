@@ -39,10 +39,10 @@ impl Joypad {
 
     /// 当按下某个按键
     pub fn keydown(&mut self, key: JoypadKey) {
+        let lines = self.lines();
         // 将按下的键位置0，代表按下该键
         self.signals &= !(key as u8);
-        // 触发手柄中断
-        self.intf.borrow_mut().hi(INTFlag::Joypad);
+        self.check_interrupt(lines);
     }
 
     /// 松开某个按键
@@ -50,6 +50,25 @@ impl Joypad {
         // 将松开的按键置1
         self.signals |= key as u8;
     }
+
+    /// 低4位输入线的状态，同时选中方向键和标准按键时两组按键的状态会叠加
+    fn lines(&self) -> u8 {
+        let mut lines = 0x0f;
+        if (self.select & 0b0001_0000) == 0x00 {
+            lines &= self.signals & 0x0f;
+        }
+        if (self.select & 0b0010_0000) == 0x00 {
+            lines &= self.signals >> 4;
+        }
+        lines
+    }
+
+    /// 只有在选中的输入线从高电平变为低电平时才触发手柄中断
+    fn check_interrupt(&mut self, old_lines: u8) {
+        if old_lines & !self.lines() & 0x0f != 0 {
+            self.intf.borrow_mut().hi(INTFlag::Joypad);
+        }
+    }
 }
 
 /// 内存地址0xff00用于记录按下的键
@@ -64,20 +83,46 @@ impl Memory for Joypad {
     fn get(&self, a: u16) -> u8 {
         // 手柄事件相关的内存地址只有0xff00
         assert_eq!(a, 0xff00);
-        if (self.select & 0b0001_0000) == 0x00 {
-            // 按下的是方向键，取signals的低4位
-            return self.select | self.signals & 0x0f;
-        }
-        if (self.select & 0b0010_0000) == 0x00 {
-            // 按下的是标准按键，取signals的高4位
-            return self.select | self.signals >> 4;
-        }
-        self.select
+        // 高2位未使用，总是读出1
+        0xc0 | (self.select & 0x30) | self.lines()
     }
 
     fn set(&mut self, a: u16, v: u8) {
         // 手柄事件相关的内存地址只有0xff00
         assert_eq!(a, 0xff00);
+        let lines = self.lines();
         self.select = v;
+        // 选中有按键按下的一组按键时，输入线同样会从高电平变为低电平
+        self.check_interrupt(lines);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_edge() {
+        let intf = Rc::new(RefCell::new(Intf::power_up()));
+        let mut joypad = Joypad::power_up(intf.clone());
+        let requested = || intf.borrow().data & (1 << INTFlag::Joypad as u8) != 0;
+        // 只选中方向键
+        joypad.set(0xff00, 0x20);
+
+        // 未选中的按键不会改变输入线
+        joypad.keydown(JoypadKey::A);
+        assert!(!requested());
+        // 输入线从高电平变为低电平
+        joypad.keydown(JoypadKey::Right);
+        assert!(requested());
+
+        intf.borrow_mut().data = 0x00;
+        // 输入线已经是低电平，或者从低电平变为高电平
+        joypad.keydown(JoypadKey::Right);
+        joypad.keyup(JoypadKey::Right);
+        assert!(!requested());
+        // 选中已经按下A的标准按键组
+        joypad.set(0xff00, 0x10);
+        assert!(requested());
+        assert_eq!(joypad.get(0xff00), 0xde);
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use crate::core::patch::{self, PatchError, PATCH_EXTENSIONS};
+
+/// Where the rom comes from
+#[derive(Clone)]
+pub enum RomSource {
+    /// A rom file, or a .zip/.gz archive containing the rom
+    Path(PathBuf),
+    /// Rom data in memory, it can also be a .zip/.gz archive
+    Bytes(Vec<u8>),
+}
+
+impl Display for RomSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomSource::Path(path) => write!(f, "{}", path.to_string_lossy()),
+            RomSource::Bytes(bytes) => write!(f, "memory ({} bytes)", bytes.len()),
+        }
+    }
+}
+
+/// Options of loading a rom
+#[derive(Clone, Default)]
+pub struct LoadOptions {
+    /// Log warnings instead of panicking when the rom has a bad logo, a bad header checksum,
+    /// unknown size codes or more data than the header declares, it's useful for rom hacks
+    /// and homebrew roms
+    pub lenient: bool,
+    /// IPS/BPS/UPS patch applied to the rom, if it's None, a patch with the same name as the
+    /// rom file is used if there is one
+    pub patch: Option<PathBuf>,
+}
+
+/// Errors happened when loading a rom
+#[derive(Debug)]
+pub enum LoadError {
+    /// Failed to read the rom file
+    Io(io::Error),
+    /// The archive is broken
+    Archive(String),
+    /// The archive doesn't contain a .gb or .gbc file
+    NoRom,
+    /// The rom extracted from the archive is larger than any cartridge
+    TooLarge,
+    /// Failed to apply the patch
+    Patch(PatchError),
+    /// The cartridge can't be powered up, e.g. bad header or unsupported type
+    Cartridge(String),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "Read rom error: {}", err),
+            LoadError::Archive(err) => write!(f, "Invalid archive: {}", err),
+            LoadError::NoRom => write!(f, "No .gb or .gbc file in archive"),
+            LoadError::TooLarge => write!(f, "Rom in archive is larger than 8MB"),
+            LoadError::Patch(err) => write!(f, "Apply patch error: {}", err),
+            LoadError::Cartridge(err) => write!(f, "Invalid cartridge: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+/// Zip local file header signature
+const ZIP_MAGIC: [u8; 4] = [0x50, 0x4b, 0x03, 0x04];
+/// Gzip member header signature
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Largest rom a cartridge can hold (512 banks), extracting stops beyond it
+const MAX_ROM_SIZE: usize = 0x4000 * 512;
+
+/// Read rom data from [source], archives are detected by their signature and extracted, then
+/// the patch is applied if there is one
+pub fn load_rom(source: RomSource, options: &LoadOptions) -> Result<Vec<u8>, LoadError> {
+    let patch_path = match (&options.patch, &source) {
+        (Some(path), _) => Some(path.clone()),
+        (None, RomSource::Path(path)) => find_patch(path),
+        (None, RomSource::Bytes(_)) => None,
+    };
+    let data = match source {
+        RomSource::Path(path) => fs::read(path)?,
+        RomSource::Bytes(bytes) => bytes,
+    };
+    let rom = if data.starts_with(&ZIP_MAGIC) {
+        unzip(data)?
+    } else if data.starts_with(&GZIP_MAGIC) {
+        extract(GzDecoder::new(data.as_slice()))?
+    } else {
+        data
+    };
+    match patch_path {
+        Some(path) => {
+            log::info!("Apply patch {}", path.to_string_lossy());
+            patch::apply(&rom, &fs::read(path)?).map_err(LoadError::Patch)
+        }
+        None => Ok(rom),
+    }
+}
+
+/// Find a patch file next to the rom with the same name
+fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| rom_path.with_extension(ext))
+        .find(|path| path.is_file())
+}
+
+/// Extract the first .gb or .gbc file from a zip archive
+fn unzip(data: Vec<u8>) -> Result<Vec<u8>, LoadError> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(data)).map_err(|err| LoadError::Archive(err.to_string()))?;
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index(i)
+            .map_err(|err| LoadError::Archive(err.to_string()))?;
+        let name = file.name().to_lowercase();
+        if !file.is_file() || !(name.ends_with(".gb") || name.ends_with(".gbc")) {
+            continue;
+        }
+        log::info!("Extract {} from zip archive", file.name());
+        // The size in the archive isn't trusted
+        return extract(file);
+    }
+    Err(LoadError::NoRom)
+}
+
+/// Read the decompressed rom, stop once it's larger than [MAX_ROM_SIZE] so a broken or
+/// malicious archive can't use up the memory
+fn extract(reader: impl Read) -> Result<Vec<u8>, LoadError> {
+    let mut rom = vec![];
+    reader.take(MAX_ROM_SIZE as u64 + 1).read_to_end(&mut rom)?;
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(LoadError::TooLarge);
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, data) in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn load(data: Vec<u8>) -> Result<Vec<u8>, LoadError> {
+        load_rom(RomSource::Bytes(data), &LoadOptions::default())
+    }
+
+    #[test]
+    fn test_load_bytes() {
+        let rom = vec![0x12; 0x8000];
+        assert_eq!(load(rom.clone()).unwrap(), rom);
+        assert_eq!(load(gzip(&rom)).unwrap(), rom);
+        let archive = zip(&[("readme.txt", b"text"), ("GAME.GBC", &rom)]);
+        assert_eq!(load(archive).unwrap(), rom);
+    }
+
+    #[test]
+    fn test_load_broken_archive() {
+        let archive = zip(&[("readme.txt", b"text")]);
+        assert!(matches!(load(archive), Err(LoadError::NoRom)));
+        let mut archive = zip(&[("game.gb", &[0; 0x100])]);
+        archive.truncate(archive.len() - 10);
+        assert!(matches!(load(archive), Err(LoadError::Archive(_))));
+
+        // The size the archive claims doesn't matter, only the data read
+        let rom = vec![0x34; 0x100];
+        let mut archive = zip(&[("game.gb", &rom)]);
+        let cd = archive
+            .windows(4)
+            .position(|v| v == [0x50, 0x4b, 0x01, 0x02])
+            .unwrap();
+        archive[cd + 24..cd + 28].copy_from_slice(&0x7fff_fff0u32.to_le_bytes());
+        assert_eq!(load(archive).unwrap(), rom);
+    }
+
+    #[test]
+    fn test_load_too_large() {
+        // Highly compressible data expands beyond any cartridge
+        let bomb = vec![0; MAX_ROM_SIZE + 1];
+        assert!(matches!(load(gzip(&bomb)), Err(LoadError::TooLarge)));
+        assert!(matches!(
+            load(zip(&[("game.gb", &bomb)])),
+            Err(LoadError::TooLarge)
+        ));
+        let rom = vec![0; MAX_ROM_SIZE];
+        assert_eq!(load(gzip(&rom)).unwrap().len(), MAX_ROM_SIZE);
+    }
+}
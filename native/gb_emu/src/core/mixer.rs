@@ -0,0 +1,171 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Sound channels of the APU
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub enum AudioChannel {
+    Square1 = 0,
+    Square2 = 1,
+    Wave = 2,
+    Noise = 3,
+}
+
+impl TryFrom<u32> for AudioChannel {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, u32> {
+        match v {
+            0 => Ok(Self::Square1),
+            1 => Ok(Self::Square2),
+            2 => Ok(Self::Wave),
+            3 => Ok(Self::Noise),
+            _ => Err(v),
+        }
+    }
+}
+
+/// Volumes the host applies on top of the game's own mixing, and taps which keep the output of
+/// each channel separately, e.g. for visualization or ripping music. Shared between the host and
+/// the emulation thread
+#[derive(Clone)]
+pub struct MixerControl {
+    inner: Arc<MixerInner>,
+}
+
+struct MixerInner {
+    /// Volume of each channel as f32 bits, a muted channel keeps its volume
+    volumes: [AtomicU32; 4],
+    muted: [AtomicBool; 4],
+    master: AtomicU32,
+    taps_enabled: AtomicBool,
+    /// Stereo output of each channel not read by the host yet
+    taps: Mutex<[Vec<(f32, f32)>; 4]>,
+}
+
+impl Default for MixerControl {
+    fn default() -> Self {
+        let one = || AtomicU32::new(1f32.to_bits());
+        Self {
+            inner: Arc::new(MixerInner {
+                volumes: [one(), one(), one(), one()],
+                muted: Default::default(),
+                master: one(),
+                taps_enabled: AtomicBool::new(false),
+                taps: Default::default(),
+            }),
+        }
+    }
+}
+
+impl MixerControl {
+    /// Set the volume of [channel], from 0.0 to 1.0
+    pub fn set_volume(&self, channel: AudioChannel, volume: f32) {
+        self.inner.volumes[channel as usize].store(volume_bits(volume), Ordering::Relaxed);
+    }
+
+    pub fn set_muted(&self, channel: AudioChannel, muted: bool) {
+        self.inner.muted[channel as usize].store(muted, Ordering::Relaxed);
+    }
+
+    /// Set the volume of the final output, from 0.0 to 1.0
+    pub fn set_master_volume(&self, volume: f32) {
+        self.inner
+            .master
+            .store(volume_bits(volume), Ordering::Relaxed);
+    }
+
+    /// Start or stop keeping the output of each channel, samples not read are dropped when it
+    /// stops
+    pub fn set_taps(&self, enabled: bool) {
+        self.inner.taps_enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.inner
+                .taps
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .for_each(Vec::clear);
+        }
+    }
+
+    /// Move the output of [channel] into [out] as interleaved left and right samples, return the
+    /// number of stereo samples moved
+    pub fn read_tap(&self, channel: AudioChannel, out: &mut [f32]) -> usize {
+        let mut taps = self.inner.taps.lock().unwrap();
+        let tap = &mut taps[channel as usize];
+        let count = tap.len().min(out.len() / 2);
+        for ((l, r), out) in tap.drain(..count).zip(out.chunks_exact_mut(2)) {
+            out.copy_from_slice(&[l, r]);
+        }
+        count
+    }
+
+    /// Gain of each channel, with mute and the master volume applied
+    pub fn gains(&self) -> [f32; 4] {
+        let load = |v: &AtomicU32| f32::from_bits(v.load(Ordering::Relaxed));
+        let master = load(&self.inner.master);
+        std::array::from_fn(|i| {
+            if self.inner.muted[i].load(Ordering::Relaxed) {
+                0.0
+            } else {
+                load(&self.inner.volumes[i]) * master
+            }
+        })
+    }
+
+    pub fn taps_enabled(&self) -> bool {
+        self.inner.taps_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Append the output of channel [i] to its tap, at most [limit] samples are kept
+    pub fn push_tap(&self, i: usize, samples: impl Iterator<Item = (f32, f32)>, limit: usize) {
+        let mut taps = self.inner.taps.lock().unwrap();
+        let tap = &mut taps[i];
+        tap.extend(samples.take(limit.saturating_sub(tap.len())));
+    }
+}
+
+/// Clamp a volume from the host to 0.0..=1.0, NaN or infinity would spread into every sample so
+/// it mutes instead
+fn volume_bits(volume: f32) -> u32 {
+    let volume = if volume.is_finite() {
+        volume.clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    volume.to_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixer_control() {
+        let mixer = MixerControl::default();
+        assert_eq!(mixer.gains(), [1.0; 4]);
+        mixer.set_volume(AudioChannel::Wave, 0.5);
+        mixer.set_muted(AudioChannel::Noise, true);
+        mixer.set_master_volume(0.5);
+        assert_eq!(mixer.gains(), [0.5, 0.5, 0.25, 0.0]);
+
+        mixer.push_tap(1, [(0.1, 0.2), (0.3, 0.4)].into_iter(), 8);
+        let mut out = [0.0; 2];
+        assert_eq!(mixer.read_tap(AudioChannel::Square2, &mut out), 1);
+        assert_eq!(out, [0.1, 0.2]);
+        assert_eq!(mixer.read_tap(AudioChannel::Square2, &mut [0.0; 8]), 1);
+    }
+
+    #[test]
+    fn test_invalid_volume() {
+        let mixer = MixerControl::default();
+        mixer.set_volume(AudioChannel::Square1, f32::NAN);
+        mixer.set_volume(AudioChannel::Square2, f32::INFINITY);
+        mixer.set_volume(AudioChannel::Wave, 2.0);
+        mixer.set_volume(AudioChannel::Noise, -1.0);
+        assert_eq!(mixer.gains(), [0.0, 0.0, 1.0, 0.0]);
+        mixer.set_master_volume(f32::NAN);
+        assert_eq!(mixer.gains(), [0.0; 4]);
+    }
+}
@@ -5,12 +5,14 @@ use std::rc::Rc;
 use crate::core::apu::APU;
 use crate::core::cartridge;
 use crate::core::cartridge::Cartridge;
+use crate::core::cheat::Cheats;
 use crate::core::convention::Term;
 use crate::core::dma::{DMAMode, DMA};
 use crate::core::gpu::GPU;
 use crate::core::hram::HRAM;
 use crate::core::intf::Intf;
 use crate::core::joypad::Joypad;
+use crate::core::loader::LoadOptions;
 use crate::core::memory::Memory;
 use crate::core::serial::Serial;
 use crate::core::speed::Speed;
@@ -21,6 +23,8 @@ use crate::core::wram::WRAM;
 pub struct MMUnit {
     // 卡带
     pub cartridge: Box<dyn Cartridge>,
+    // 金手指，Game Genie修改卡带rom的读取结果，GameShark每帧修改一次ram
+    pub cheats: Cheats,
     // 音频处理器
     pub apu: Option<APU>,
     // 视频处理器
@@ -49,12 +53,13 @@ pub struct MMUnit {
 }
 
 impl MMUnit {
-    pub fn power_up<T: AsRef<Path>>(path: T, save_path: T) -> Self {
-        let cartridge = cartridge::power_up(path, save_path);
+    pub fn power_up(rom: Vec<u8>, save_path: impl AsRef<Path>, options: &LoadOptions) -> Self {
+        let cartridge = cartridge::power_up(rom, save_path, options);
         let term = cartridge.term();
         let intf = Rc::new(RefCell::new(Intf::power_up()));
         let mut mmunit = Self {
             cartridge,
+            cheats: Cheats::default(),
             apu: None,
             gpu: GPU::power_up(term, intf.clone()),
             joypad: Joypad::power_up(intf.clone()),
@@ -70,7 +75,7 @@ impl MMUnit {
             hram: HRAM::power_up(),
         };
         mmunit.init();
-        return mmunit;
+        mmunit
     }
 
     /// 初始化某些内存的数据
@@ -117,13 +122,20 @@ impl MMUnit {
         let cpu_cycles = cycles + dma_cost * cpu_speed;
         self.timer.next(cpu_cycles);
         self.gpu.next(gpu_cycles);
-        // if let Some(apu) = &mut self.apu
-        // {
-        //     apu.next(gpu_cycles);
-        // }
+        self.cartridge.tick(gpu_cycles);
+        if let Some(apu) = &mut self.apu {
+            apu.next(gpu_cycles);
+        }
         return gpu_cycles;
     }
 
+    /// 写入GameShark金手指，每帧执行一次
+    pub fn apply_cheats(&mut self) {
+        let cheats = std::mem::take(&mut self.cheats);
+        cheats.apply_shark(self);
+        self.cheats = cheats;
+    }
+
     /// 执行dma数据拷贝，返回消耗的CPU时钟周期
     fn run_dma(&mut self) -> u32 {
         if !self.dma.active {
@@ -175,7 +187,7 @@ impl Memory for MMUnit {
     fn get(&self, a: u16) -> u8 {
         match a {
             // 卡带
-            0x0000..=0x7fff => self.cartridge.get(a),
+            0x0000..=0x7fff => self.cheats.patch_rom(a, self.cartridge.get(a)),
             // GPU
             0x8000..=0x9fff => self.gpu.get(a),
             // 卡带
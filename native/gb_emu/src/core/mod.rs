@@ -1,5 +1,10 @@
 pub mod memory;
 pub mod cartridge;
+pub mod header;
+pub mod camera;
+pub mod cheat;
+pub mod loader;
+pub mod patch;
 pub mod cpu;
 pub mod register;
 pub mod convention;
@@ -10,6 +15,7 @@ pub mod motherboard;
 pub mod gpu;
 pub mod dma;
 pub mod apu;
+pub mod mixer;
 pub mod joypad;
 pub mod serial;
 pub mod timer;
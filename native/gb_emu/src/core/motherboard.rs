@@ -2,9 +2,10 @@ use std::cell::RefCell;
 use std::path::Path;
 use std::rc::Rc;
 
+use crate::core::loader::LoadOptions;
 use crate::core::memory::Memory;
 use crate::core::mmunit::MMUnit;
-use crate::core::rtc::RTC;
+use crate::core::rtc::{SyncMode, RTC};
 
 // 主板，cup与MMU交互，MMU负责管理硬件外设
 pub struct MotherBoard {
@@ -13,8 +14,9 @@ pub struct MotherBoard {
 }
 
 impl MotherBoard {
-    pub fn power_up<T: AsRef<Path>>(path: T, save_path: T) -> Self {
-        let mmu = Rc::new(RefCell::new(MMUnit::power_up(path, save_path)));
+    // 使用已经加载（解压、打过补丁）的rom启动
+    pub fn power_up(rom: Vec<u8>, save_path: impl AsRef<Path>, options: &LoadOptions) -> Self {
+        let mmu = Rc::new(RefCell::new(MMUnit::power_up(rom, save_path, options)));
         let rtc = RTC::power_up(mmu.borrow().term, mmu.clone());
         Self { mmu, rtc }
     }
@@ -28,9 +30,25 @@ impl MotherBoard {
         cycles
     }
 
+    // 切换控制模拟速度的方式
+    pub fn set_sync(&mut self, mode: SyncMode) {
+        let mut mmu = self.mmu.borrow_mut();
+        self.rtc.sync = match &mut mmu.apu {
+            Some(apu) => {
+                apu.set_audio_sync(matches!(mode, SyncMode::Audio));
+                mode
+            }
+            // 没有APU时无法通过音频同步，只能使用休眠的方式
+            None => SyncMode::Video,
+        };
+    }
+
     pub fn check_and_reset_gpu_updated(&mut self) -> bool {
         let is_vblank = self.mmu.borrow().gpu.v_blank;
         self.mmu.borrow_mut().gpu.v_blank = false;
+        if is_vblank {
+            self.mmu.borrow_mut().apply_cheats();
+        }
         is_vblank
     }
 }
@@ -0,0 +1,457 @@
+use std::fmt::{Display, Formatter};
+
+/// Errors happened when applying a patch to the rom
+#[derive(Debug)]
+pub enum PatchError {
+    /// The patch isn't an IPS, BPS or UPS file
+    UnknownFormat,
+    /// The patch is truncated or contains invalid data
+    Corrupted(&'static str),
+    /// The rom isn't the one the patch is made for
+    SourceSize {
+        expected: usize,
+        actual: usize,
+    },
+    SourceCrc {
+        expected: u32,
+        actual: u32,
+    },
+    /// The patched rom is different from the one the patch is made for
+    TargetCrc {
+        expected: u32,
+        actual: u32,
+    },
+    /// The patch file itself is damaged
+    PatchCrc {
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "Unknown patch format"),
+            PatchError::Corrupted(msg) => write!(f, "Corrupted patch: {}", msg),
+            PatchError::SourceSize { expected, actual } => write!(
+                f,
+                "Patch expects a rom of {} bytes, got {} bytes",
+                expected, actual
+            ),
+            PatchError::SourceCrc { expected, actual } => write!(
+                f,
+                "Rom crc mismatch, expected {:08x}, got {:08x}",
+                expected, actual
+            ),
+            PatchError::TargetCrc { expected, actual } => write!(
+                f,
+                "Patched rom crc mismatch, expected {:08x}, got {:08x}",
+                expected, actual
+            ),
+            PatchError::PatchCrc { expected, actual } => write!(
+                f,
+                "Patch crc mismatch, expected {:08x}, got {:08x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+/// Patch file extensions, in the order they are searched next to the rom
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "bps", "ups"];
+
+/// Apply an IPS, BPS or UPS [patch] to [rom], the format is detected by its signature
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+/// Largest rom a cartridge can hold (512 banks), a bigger target size comes from a broken patch
+const MAX_TARGET_SIZE: usize = 0x4000 * 512;
+
+const OVERFLOW: PatchError = PatchError::Corrupted("number overflow");
+
+/// Read bytes from the patch sequentially
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], PatchError> {
+        if self.data.len() - self.pos < n {
+            return Err(PatchError::Corrupted("unexpected end of patch"));
+        }
+        let v = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(v)
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Big endian number of [n] bytes, used by IPS
+    fn be(&mut self, n: usize) -> Result<usize, PatchError> {
+        Ok(self.bytes(n)?.iter().fold(0, |v, b| (v << 8) | *b as usize))
+    }
+
+    /// Variable length number used by BPS and UPS
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut v: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let x = self.u8()? as usize;
+            v = shift
+                .checked_mul(x & 0x7f)
+                .and_then(|n| v.checked_add(n))
+                .ok_or(OVERFLOW)?;
+            if x & 0x80 != 0 {
+                return Ok(v);
+            }
+            shift = shift.checked_mul(0x80).ok_or(OVERFLOW)?;
+            v = v.checked_add(shift).ok_or(OVERFLOW)?;
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+/// IPS: a list of records (3 bytes offset, 2 bytes size, data), a record of size 0 is run-length
+/// encoded. It has no checksum, an optional 3 bytes size after "EOF" truncates the rom
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut r = Reader::new(patch, 5);
+    loop {
+        let head = r.bytes(3)?;
+        if head == b"EOF" {
+            break;
+        }
+        let offset = head.iter().fold(0, |v, b| (v << 8) | *b as usize);
+        let size = r.be(2)?;
+        let (size, data) = if size == 0 {
+            let size = r.be(2)?;
+            (size, vec![r.u8()?; size])
+        } else {
+            (size, r.bytes(size)?.to_vec())
+        };
+        if out.len() < offset + size {
+            out.resize(offset + size, 0x00);
+        }
+        out[offset..offset + size].copy_from_slice(&data);
+    }
+    if let Ok(size) = r.be(3) {
+        out.truncate(size);
+    }
+    Ok(out)
+}
+
+/// Split the 12 bytes crc footer (source, target, patch) shared by BPS and UPS, the patch crc is
+/// verified here
+fn footer(patch: &[u8]) -> Result<(&[u8], u32, u32), PatchError> {
+    if patch.len() < 16 {
+        return Err(PatchError::Corrupted("missing checksums"));
+    }
+    let (body, crcs) = patch.split_at(patch.len() - 12);
+    let crc = |i: usize| u32::from_le_bytes(crcs[i * 4..i * 4 + 4].try_into().unwrap());
+    let actual = crc32fast::hash(&patch[..patch.len() - 4]);
+    if actual != crc(2) {
+        return Err(PatchError::PatchCrc {
+            expected: crc(2),
+            actual,
+        });
+    }
+    Ok((body, crc(0), crc(1)))
+}
+
+fn check_source(rom: &[u8], size: usize, crc: u32) -> Result<(), PatchError> {
+    if rom.len() != size {
+        return Err(PatchError::SourceSize {
+            expected: size,
+            actual: rom.len(),
+        });
+    }
+    let actual = crc32fast::hash(rom);
+    if actual != crc {
+        return Err(PatchError::SourceCrc {
+            expected: crc,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Reject a target size read from the patch before allocating it
+fn check_target_size(size: usize) -> Result<usize, PatchError> {
+    if size > MAX_TARGET_SIZE {
+        return Err(PatchError::Corrupted("target size too large"));
+    }
+    Ok(size)
+}
+
+fn check_target(out: &[u8], crc: u32) -> Result<(), PatchError> {
+    let actual = crc32fast::hash(out);
+    if actual != crc {
+        return Err(PatchError::TargetCrc {
+            expected: crc,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+const OUT_OF_RANGE: PatchError = PatchError::Corrupted("copy out of range");
+
+/// BPS: the target is built with 4 kinds of actions copying from the source, the patch or the
+/// target itself
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (body, source_crc, target_crc) = footer(patch)?;
+    let mut r = Reader::new(body, 4);
+    let source_size = r.varint()?;
+    let target_size = check_target_size(r.varint()?)?;
+    let metadata_size = r.varint()?;
+    r.bytes(metadata_size)?;
+    check_source(rom, source_size, source_crc)?;
+
+    let mut out = Vec::with_capacity(target_size);
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+    while !r.done() {
+        let data = r.varint()?;
+        let len = (data >> 2) + 1;
+        if out.len() + len > target_size {
+            return Err(PatchError::Corrupted("target overflow"));
+        }
+        match data & 0x03 {
+            // SourceRead
+            0 => {
+                let start = out.len();
+                out.extend_from_slice(rom.get(start..start + len).ok_or(OUT_OF_RANGE)?);
+            }
+            // TargetRead
+            1 => out.extend_from_slice(r.bytes(len)?),
+            // SourceCopy
+            2 => {
+                source_offset = move_offset(source_offset, signed(r.varint()?))?;
+                let start = usize::try_from(source_offset).map_err(|_| OUT_OF_RANGE)?;
+                out.extend_from_slice(rom.get(start..start + len).ok_or(OUT_OF_RANGE)?);
+                source_offset = move_offset(source_offset, len as isize)?;
+            }
+            // TargetCopy, the range may overlap with the bytes being written
+            _ => {
+                target_offset = move_offset(target_offset, signed(r.varint()?))?;
+                let start = usize::try_from(target_offset).map_err(|_| OUT_OF_RANGE)?;
+                if start >= out.len() {
+                    return Err(OUT_OF_RANGE);
+                }
+                for i in start..start + len {
+                    out.push(out[i]);
+                }
+                target_offset = move_offset(target_offset, len as isize)?;
+            }
+        }
+    }
+    if out.len() != target_size {
+        return Err(PatchError::Corrupted("target size mismatch"));
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+/// Add a relative offset read from the patch, a broken patch may overflow it
+fn move_offset(offset: isize, delta: isize) -> Result<isize, PatchError> {
+    offset.checked_add(delta).ok_or(OUT_OF_RANGE)
+}
+
+/// Relative offset in BPS, bit 0 is the sign
+fn signed(v: usize) -> isize {
+    let n = (v >> 1) as isize;
+    if v & 0x01 != 0 {
+        -n
+    } else {
+        n
+    }
+}
+
+/// UPS: a list of hunks (relative offset, bytes to xor terminated by 0), the patch works in both
+/// directions
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (body, crc_a, crc_b) = footer(patch)?;
+    let mut r = Reader::new(body, 4);
+    let size_a = r.varint()?;
+    let size_b = r.varint()?;
+    // Reverse the patch if the rom is the patched one
+    let (source_size, target_size, source_crc, target_crc) =
+        if rom.len() == size_b && crc32fast::hash(rom) == crc_b {
+            (size_b, size_a, crc_b, crc_a)
+        } else {
+            (size_a, size_b, crc_a, crc_b)
+        };
+    check_source(rom, source_size, source_crc)?;
+    let target_size = check_target_size(target_size)?;
+
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0x00);
+    let mut offset: usize = 0;
+    while !r.done() {
+        offset = offset.checked_add(r.varint()?).ok_or(OUT_OF_RANGE)?;
+        loop {
+            let x = r.u8()?;
+            if let Some(b) = out.get_mut(offset) {
+                *b ^= x;
+            }
+            offset = offset.checked_add(1).ok_or(OUT_OF_RANGE)?;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut v: usize, out: &mut Vec<u8>) {
+        loop {
+            let x = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                out.push(0x80 | x);
+                return;
+            }
+            out.push(x);
+            v -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend(crc32fast::hash(source).to_le_bytes());
+        patch.extend(crc32fast::hash(target).to_le_bytes());
+        patch.extend(crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_ips() {
+        let rom = [0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x01, 0x00, 0x02, 0xaa, 0xbb]);
+        patch.extend([0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x03, 0xcc]);
+        patch.extend(b"EOF");
+        let out = apply(&rom, &patch).unwrap();
+        assert_eq!(out, [0, 0xaa, 0xbb, 0, 0, 0, 0, 0, 0xcc, 0xcc, 0xcc]);
+    }
+
+    #[test]
+    fn test_bps() {
+        let rom = b"hello world".to_vec();
+        let target = b"hello hello!".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        varint(rom.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        // SourceRead "hello "
+        varint(5 << 2, &mut patch);
+        // TargetCopy "hello" from offset 0
+        varint((4 << 2) | 3, &mut patch);
+        varint(0, &mut patch);
+        // TargetRead "!"
+        varint(1, &mut patch);
+        patch.push(b'!');
+        let patch = with_footer(patch, &rom, &target);
+        assert_eq!(apply(&rom, &patch).unwrap(), target);
+
+        let other = b"hello wOrld".to_vec();
+        assert!(matches!(
+            apply(&other, &patch),
+            Err(PatchError::SourceCrc { .. })
+        ));
+        let mut broken = patch.clone();
+        broken[8] ^= 0x01;
+        assert!(matches!(
+            apply(&rom, &broken),
+            Err(PatchError::PatchCrc { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ups() {
+        let rom = b"abcdef".to_vec();
+        let target = b"abXdefgh".to_vec();
+        let mut patch = b"UPS1".to_vec();
+        varint(rom.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(2, &mut patch);
+        patch.extend([b'c' ^ b'X', 0x00]);
+        varint(2, &mut patch);
+        patch.extend([b'g', b'h', 0x00]);
+        let patch = with_footer(patch, &rom, &target);
+        assert_eq!(apply(&rom, &patch).unwrap(), target);
+        // UPS patches can be reverted
+        assert_eq!(apply(&target, &patch).unwrap(), rom);
+        assert!(matches!(
+            apply(b"abcde", &patch),
+            Err(PatchError::SourceSize { .. })
+        ));
+    }
+
+    #[test]
+    fn test_target_size_too_large() {
+        let rom = b"abcdef".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        varint(rom.len(), &mut patch);
+        varint(MAX_TARGET_SIZE + 1, &mut patch);
+        varint(0, &mut patch);
+        let patch = with_footer(patch, &rom, &rom);
+        assert!(matches!(
+            apply(&rom, &patch),
+            Err(PatchError::Corrupted("target size too large"))
+        ));
+    }
+
+    #[test]
+    fn test_offset_overflow() {
+        let rom = b"abcdef".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        varint(rom.len(), &mut patch);
+        varint(rom.len(), &mut patch);
+        varint(0, &mut patch);
+        // SourceCopy "a" from offset 0, then jump beyond isize::MAX
+        varint(2, &mut patch);
+        varint(0, &mut patch);
+        varint(2, &mut patch);
+        varint(usize::MAX - 1, &mut patch);
+        let patch = with_footer(patch, &rom, &rom);
+        assert!(matches!(
+            apply(&rom, &patch),
+            Err(PatchError::Corrupted("copy out of range"))
+        ));
+
+        let mut patch = b"UPS1".to_vec();
+        varint(rom.len(), &mut patch);
+        varint(rom.len(), &mut patch);
+        varint(usize::MAX, &mut patch);
+        patch.push(0x00);
+        let patch = with_footer(patch, &rom, &rom);
+        assert!(matches!(
+            apply(&rom, &patch),
+            Err(PatchError::Corrupted("copy out of range"))
+        ));
+    }
+}
@@ -13,14 +13,37 @@ pub const STEP_TIME: u32 = 16;
 // 规定每段时间内最多执行的时钟周期
 pub const STEP_CYCLES: u32 = ((CLOCK_FREQUENCY as f64 / 1000f64) * STEP_TIME as f64) as u32;
 
+/// How emulation is kept at the speed of the real Game Boy
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub enum SyncMode {
+    /// Sleep until the real time catches up with the emulated time, for video-only use
+    Video = 0,
+    /// Wait for the host to play the audio buffer, the sound never crackles but the frame rate
+    /// follows the audio device. It needs the APU, otherwise Video is used
+    Audio = 1,
+}
+
+impl TryFrom<u32> for SyncMode {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, u32> {
+        match v {
+            0 => Ok(Self::Video),
+            1 => Ok(Self::Audio),
+            _ => Err(v),
+        }
+    }
+}
+
 pub struct RTC {
     pub cpu: Cpu,
+    // 控制模拟速度的方式
+    pub sync: SyncMode,
     // 累计已执行的时钟周期，超出指定范围时重新计数
     step_cycles: u32,
     // 最近一次开始累计已执行的时钟周期
     step_zero: time::Instant,
-    // 是否已重新累计执行的时钟周期
-    step_flip: bool,
 }
 
 impl RTC {
@@ -28,9 +51,9 @@ impl RTC {
         let cpu = Cpu::power_up(term, mem);
         Self {
             cpu,
+            sync: SyncMode::Video,
             step_cycles: 0,
             step_zero: time::Instant::now(),
-            step_flip: false,
         }
     }
 
@@ -39,24 +62,11 @@ impl RTC {
     pub fn next(&mut self) -> u32 {
         if self.step_cycles > STEP_CYCLES {
             // 规定时间段内执行的时钟周期达到上限
-            self.step_flip = true;
             self.step_cycles -= STEP_CYCLES;
-            let now = time::Instant::now();
-            // 距离开始累计执行时钟周期过了多久
-            let d = now.duration_since(self.step_zero);
-            // 距离规定时间段结束还要多久
-            let s = u64::from(STEP_TIME.saturating_sub(d.as_millis() as u32));
-            // CPU休眠到下个规定的时间段
-            thread::sleep(Duration::from_millis(s));
-            // 重置开始累计执行时钟周期的时间
-            self.step_zero = self.step_zero.checked_add(
-                Duration::from_millis(u64::from(STEP_TIME))
-            ).unwrap();
-
-            // 正常情况下，此时的step_zero要在now之后，但是sleep函数通常会比设定的时间睡眠的更久，累计的误差可能会
-            // 使now在step_zero之后，当出现这种情况时要将step_zero设定为now，清空sleep导致的误差
-            if now.checked_duration_since(self.step_zero).is_some() {
-                self.step_zero = now;
+            match self.sync {
+                SyncMode::Video => self.sleep(),
+                // 模拟速度由APU等待宿主播放音频来控制，这里只记录时间段的起点
+                SyncMode::Audio => self.step_zero = time::Instant::now(),
             }
         }
         // 累计cpu执行下一条指令花费的时钟周期
@@ -65,12 +75,29 @@ impl RTC {
         cycles
     }
 
-    // 用于判断是否产生了新的一帧
-    pub fn flip(&mut self) -> bool {
-        let r = self.step_flip;
-        if r {
-            self.step_flip = false;
+    // CPU休眠到下个规定的时间段
+    fn sleep(&mut self) {
+        let now = time::Instant::now();
+        // 距离开始累计执行时钟周期过了多久
+        let d = now.duration_since(self.step_zero);
+        // 距离规定时间段结束还要多久
+        let s = u64::from(STEP_TIME.saturating_sub(d.as_millis() as u32));
+        thread::sleep(Duration::from_millis(s));
+        // 重置开始累计执行时钟周期的时间
+        self.step_zero = self.step_zero.checked_add(
+            Duration::from_millis(u64::from(STEP_TIME))
+        ).unwrap();
+
+        // 正常情况下，此时的step_zero要在now之后，但是sleep函数通常会比设定的时间睡眠的更久，累计的误差可能会
+        // 使now在step_zero之后，当出现这种情况时要将step_zero设定为now，清空sleep导致的误差
+        if now.checked_duration_since(self.step_zero).is_some() {
+            self.step_zero = now;
         }
-        r
+    }
+
+    // 模拟时间对应的现实时间，即当前时间段的起始时间加上已执行的时钟周期所需的时间
+    pub fn emulated_instant(&self) -> time::Instant {
+        let nanos = u64::from(self.step_cycles) * 1_000_000_000 / u64::from(CLOCK_FREQUENCY);
+        self.step_zero + Duration::from_nanos(nanos)
     }
 }
\ No newline at end of file
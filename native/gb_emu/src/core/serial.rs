@@ -11,6 +11,8 @@ pub struct Serial {
     /// Bit 1: 时钟速度，0表示Normal，1表示Fast（仅CGB模式）
     /// Bit 0: 移位时钟，0表示外部时钟，1表示内部时钟
     control: u8,
+    /// 使用内部时钟开始传输时发送出去的字节，等待主机取走
+    pub sent: Vec<u8>,
 }
 
 impl Serial {
@@ -18,6 +20,7 @@ impl Serial {
         Self {
             data: 0x00,
             control: 0x00,
+            sent: vec![],
         }
     }
 }
@@ -34,7 +37,12 @@ impl Memory for Serial {
     fn set(&mut self, a: u16, v: u8) {
         match a {
             0xff01 => self.data = v,
-            0xff02 => self.control = v,
+            0xff02 => {
+                self.control = v;
+                if v & 0x81 == 0x81 {
+                    self.sent.push(self.data);
+                }
+            }
             _ => unreachable!(),
         }
     }
@@ -1,118 +1,865 @@
+use std::any::Any;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread;
-use std::thread::Thread;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
+use crate::core::apu::APU;
+use crate::core::camera::CameraSource;
+use crate::core::cheat::{CheatError, Cheats};
 use crate::core::convention::{SCREEN_H, SCREEN_W};
+use crate::core::header;
+use crate::core::loader::{self, LoadError, LoadOptions, RomSource};
+use crate::core::memory::Memory;
+use crate::core::mixer::{AudioChannel, MixerControl};
 use crate::core::motherboard::MotherBoard;
+use crate::core::rtc::SyncMode;
+use crate::device::input::{InputSource, OppositePolicy};
 use crate::device::keyboard::{GbBtn, Keyboard, KEY_MAPS};
+use crate::device::movie::{MovieRequest, MovieSession};
+use crate::device::pixel::PixelFormat;
+use crate::device::recorder::Recorder;
 use crate::device::window::{Window, WindowConfig};
+use crate::tools::event::{Event, EventSink};
+use crate::tools::png;
 
-pub struct Emulator {
+/// Queued input changes are checked every scanline of emulated time
+const INPUT_STEP_CYCLES: u32 = 456;
+
+/// How many frames between two autosaves of battery backed ram (about 5 seconds)
+const AUTOSAVE_FRAMES: u32 = 300;
+
+/// How long the host waits for the reply of a [Command]
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sample rate of the audio read by the host
+pub const AUDIO_SAMPLE_RATE: u32 = 48_000;
+
+/// Requests sent from the host thread, they are handled by the emulation thread between frames
+pub enum Command {
+    /// Write battery backed ram to disk now, reply whether it succeeded
+    FlushSave(Sender<bool>),
+    /// Replace battery backed ram with a .sav file, reply whether it succeeded
+    ImportSave(Vec<u8>, Sender<bool>),
+    /// Reply battery backed ram in .sav format
+    ExportSave(Sender<Vec<u8>>),
+    /// Stop recording or playing the movie, reply whether the movie was saved
+    StopMovie(Sender<bool>),
+    /// Start recording video and audio to an AVI file, reply whether the file is created
+    StartRecording(PathBuf, Sender<bool>),
+    /// Stop recording, reply whether the file is saved
+    StopRecording(Sender<bool>),
+    /// Restart the game, a hard reset reloads the rom and cheats from disk as well. Reply whether
+    /// the game restarted
+    Reset(bool, Sender<bool>),
+    /// Switch to another rom and its save path, reply whether the rom is loaded
+    LoadRom(RomSource, String, Sender<bool>),
+}
+
+/// State shared by the host and the emulation thread, everything in it is thread safe
+struct Shared {
     window: Window,
+    /// Title of the running game, empty before the first one is loaded
+    rom_title: Mutex<String>,
     keyboard: Keyboard,
+    /// Image source of the Game Boy Camera, supplied by the host
+    camera: CameraSource,
+    /// Stereo samples the APU produced and the host hasn't read yet
+    audio: Arc<Mutex<Vec<(f32, f32)>>>,
+    /// Volumes and channel taps of the APU
+    mixer: MixerControl,
+    /// How the emulation thread keeps the speed of the real Game Boy
+    sync_mode: Mutex<SyncMode>,
+    sync_changed: AtomicBool,
+    /// Cheats of the running game, edited by the host and copied to the emulation thread
+    cheats: Mutex<Cheats>,
+    cheats_changed: AtomicBool,
+    /// Addresses the emulator pauses at before executing them
+    breakpoints: Mutex<HashSet<u16>>,
+    breakpoints_changed: AtomicBool,
+    events: EventSink,
+    /// Why the last run crashed, None if it didn't
+    crash_report: Mutex<Option<String>>,
     is_running: AtomicBool,
     is_pause: AtomicBool,
 }
 
+/// An emulator owned by the host. Each emulator runs the game in its own thread, so several of
+/// them can run at the same time. The thread is joined when the emulator exits or is dropped
+pub struct Emulator {
+    shared: Arc<Shared>,
+    /// Options used by the next [run]
+    load_options: LoadOptions,
+    /// Movie to record or play on the next [run]
+    movie_request: Option<MovieRequest>,
+    /// Sends commands to the emulation thread of the current run
+    cmd_sender: Option<Sender<Command>>,
+    thread: Option<JoinHandle<()>>,
+}
+
 impl Emulator {
     pub fn create(win_config: &WindowConfig) -> Self {
-        Self {
+        let shared = Shared {
             window: Window::create(win_config),
+            rom_title: Mutex::new(String::new()),
             keyboard: Keyboard::create(),
+            camera: CameraSource::default(),
+            audio: Arc::new(Mutex::new(Vec::new())),
+            mixer: MixerControl::default(),
+            sync_mode: Mutex::new(SyncMode::Video),
+            sync_changed: AtomicBool::new(false),
+            cheats: Mutex::new(Cheats::default()),
+            cheats_changed: AtomicBool::new(false),
+            breakpoints: Mutex::new(HashSet::new()),
+            breakpoints_changed: AtomicBool::new(false),
+            events: EventSink::default(),
+            crash_report: Mutex::new(None),
             is_running: AtomicBool::new(false),
             is_pause: AtomicBool::new(false),
+        };
+        Self {
+            shared: Arc::new(shared),
+            load_options: LoadOptions::default(),
+            movie_request: None,
+            cmd_sender: None,
+            thread: None,
         }
     }
 
-    // This method will called in new thread
-    pub fn run(&mut self, rom_path: &str, save_path: &str) {
-        if self.is_running.load(Ordering::Acquire) {
-            log::warn!("{} is already running", rom_path);
+    /// Run [source] in a new emulation thread
+    pub fn run(&mut self, source: RomSource, save_path: String) {
+        if self.shared.is_running.swap(true, Ordering::AcqRel) {
+            log::warn!("{} is already running", source);
             return;
         }
+        // The last run has stopped by itself, e.g. the rom failed to load
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        *self.shared.crash_report.lock().unwrap() = None;
+        // Cheats of the last run belong to another game
+        *self.shared.cheats.lock().unwrap() = Cheats::default();
+        let (cmd_sender, cmd_receiver) = channel();
+        self.cmd_sender = Some(cmd_sender);
+        let runner = Runner {
+            shared: self.shared.clone(),
+            cmd_receiver,
+            options: self.load_options.clone(),
+            source: source.clone(),
+            rom: vec![],
+            save_path: save_path.clone(),
+            movie: None,
+            recorder: None,
+            persist: true,
+            frame: 0,
+            rumble: false,
+            pc: 0,
+        };
+        let movie_request = self.movie_request.take();
+        self.thread = Some(thread::spawn(move || {
+            runner.run(source, save_path, movie_request);
+        }));
+    }
+
+    /// Send a command to the emulation thread and wait for its reply, return None if the
+    /// emulator is not running or doesn't reply in time
+    pub fn request<T>(&self, cmd: impl FnOnce(Sender<T>) -> Command) -> Option<T> {
+        let (sender, thread) = match (&self.cmd_sender, &self.thread) {
+            (Some(sender), Some(thread)) if self.is_running() => (sender, thread),
+            _ => return None,
+        };
+        let (reply, result) = channel();
+        sender.send(cmd(reply)).ok()?;
+        // Wake up the emulation thread in case it's paused
+        thread.thread().unpark();
+        result.recv_timeout(COMMAND_TIMEOUT).ok()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.shared.is_running.load(Ordering::Acquire)
+    }
+
+    pub fn pause(&self) {
+        self.shared.is_pause.store(true, Ordering::Release);
+        log::info!("Pause emulator");
+    }
+
+    pub fn resume(&self) {
+        self.shared.is_pause.store(false, Ordering::Release);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+        log::info!("Resume emulator");
+    }
+
+    /// Stop the emulation thread and wait until the game is saved
+    pub fn exit(&mut self) {
+        self.shared.is_running.store(false, Ordering::Release);
+        self.cmd_sender = None;
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            if thread.join().is_err() {
+                log::error!("Emulation thread panicked");
+                let msg = String::from("Emulation thread panicked");
+                self.shared.events.post(Event::Crashed(msg));
+            }
+            log::info!("Exit emulator");
+        }
+    }
+
+    /// Whether roms with bad checksums or non-standard sizes are loaded with warnings, it takes
+    /// effect on the next run
+    pub fn set_lenient_loading(&mut self, lenient: bool) {
+        self.load_options.lenient = lenient;
+    }
+
+    /// Use the IPS/BPS/UPS patch at [path] instead of searching one next to the rom, it takes
+    /// effect on the next run
+    pub fn set_patch(&mut self, path: Option<PathBuf>) {
+        self.load_options.patch = path;
+    }
+
+    /// Record a movie from power-on on the next run
+    pub fn record_movie(&mut self, path: PathBuf) {
+        self.movie_request = Some(MovieRequest::Record(path));
+    }
+
+    /// Play a movie on the next run, battery backed ram isn't saved during the run
+    pub fn play_movie(&mut self, path: PathBuf) {
+        self.movie_request = Some(MovieRequest::Play(path));
+    }
+
+    /// Make [btn] pressed and released every [frames] frames while it's held, 0 disables turbo
+    pub fn set_turbo(&self, btn: GbBtn, frames: u8) {
+        self.shared.keyboard.set_turbo(btn, frames);
+        log::info!("Set turbo of {} button to {} frames", btn, frames);
+    }
+
+    pub fn define_macro(&self, name: &str, steps: &str) -> bool {
+        self.shared.keyboard.define_macro(name, steps)
+    }
+
+    pub fn trigger_macro(&self, name: &str) -> bool {
+        self.shared.keyboard.trigger_macro(name)
+    }
+
+    pub fn press_button(&self, btn: GbBtn) {
+        self.shared.keyboard.press_button(btn);
+        log::info!("Press {} button", btn);
+    }
+
+    pub fn release_button(&self, btn: GbBtn) {
+        self.shared.keyboard.release_button(btn);
+        log::info!("Release {} button", btn);
+    }
+
+    pub fn key_down(&self, source: InputSource, code: u32) {
+        self.shared.keyboard.key_down(source, code);
+    }
+
+    pub fn key_up(&self, source: InputSource, code: u32) {
+        self.shared.keyboard.key_up(source, code);
+    }
+
+    /// Map key [code] of [source] to [buttons], a combination of [GbBtn] bits
+    pub fn map_key(&self, source: InputSource, code: u32, buttons: u8) {
+        self.shared.keyboard.map_key(source, code, buttons);
+        log::info!("Map key {} to buttons {:#04x}", code, buttons);
+    }
+
+    pub fn set_opposite_policy(&self, policy: OppositePolicy) {
+        self.shared.keyboard.set_opposite_policy(policy);
+    }
+
+    /// Feed a 128x112 grayscale frame to the Game Boy Camera sensor
+    pub fn set_camera_frame(&self, gray: &[u8]) {
+        self.shared.camera.set_frame(gray);
+    }
+
+    /// The latest frame and its sequence number, it stays valid until the next call.
+    ///
+    /// # Safety
+    ///
+    /// Same as [Window::get_buffer], only one thread may get frames
+    pub unsafe fn get_window_frame(&self) -> (*const u32, u64) {
+        let (pixels, seq) = self.shared.window.get_buffer();
+        (pixels.as_ptr(), seq)
+    }
+
+    /// Copy the native 160x144 frame into [out], return its sequence number, or None if [out] is
+    /// too small
+    pub fn copy_native_frame(&self, format: PixelFormat, out: &mut [u8]) -> Option<u64> {
+        self.shared.window.copy_native(format, out)
+    }
+
+    /// Write the last frame to [path] as PNG, at 160x144 or as it's shown in the window. With
+    /// [metadata] the game title and the frame sequence number are stored in text chunks
+    pub fn save_screenshot(&self, path: &Path, scaled: bool, metadata: bool) -> io::Result<()> {
+        let window = &self.shared.window;
+        let (pixels, seq) = window.native_frame();
+        let (width, height, pixels) = if scaled {
+            (window.width, window.height, window.snapshot())
+        } else {
+            (u32::from(SCREEN_W), u32::from(SCREEN_H), pixels)
+        };
+        let title = self.shared.rom_title.lock().unwrap().clone();
+        let seq = seq.to_string();
+        let texts = if metadata {
+            vec![("Title", title.as_str()), ("Frame", seq.as_str())]
+        } else {
+            vec![]
+        };
+        fs::write(path, png::encode(width, height, &pixels, &texts))?;
+        log::info!("Save screenshot to {}", path.to_string_lossy());
+        Ok(())
+    }
+
+    /// Blend each frame with the previous one like a slow LCD, 0 turns it off
+    pub fn set_ghosting(&self, persistence: f32) {
+        self.shared.window.effects.set_ghosting(persistence);
+    }
+
+    pub fn set_pixel_grid(&self, enabled: bool) {
+        self.shared.window.effects.set_grid(enabled);
+    }
+
+    pub fn set_dmg_tint(&self, enabled: bool) {
+        self.shared.window.effects.set_dmg_tint(enabled);
+    }
+
+    pub fn set_channel_volume(&self, channel: AudioChannel, volume: f32) {
+        self.shared.mixer.set_volume(channel, volume);
+    }
+
+    pub fn set_channel_muted(&self, channel: AudioChannel, muted: bool) {
+        self.shared.mixer.set_muted(channel, muted);
+    }
+
+    pub fn set_master_volume(&self, volume: f32) {
+        self.shared.mixer.set_master_volume(volume);
+    }
+
+    pub fn set_sync_mode(&self, mode: SyncMode) {
+        *self.shared.sync_mode.lock().unwrap() = mode;
+        self.shared.sync_changed.store(true, Ordering::Release);
+    }
+
+    pub fn set_channel_taps(&self, enabled: bool) {
+        self.shared.mixer.set_taps(enabled);
+    }
+
+    /// Move samples of [channel] into [out] as interleaved stereo, return the number moved
+    pub fn read_channel_tap(&self, channel: AudioChannel, out: &mut [f32]) -> usize {
+        self.shared.mixer.read_tap(channel, out)
+    }
+
+    /// Move the mixed audio into [out] as interleaved stereo at [AUDIO_SAMPLE_RATE], return the
+    /// number of stereo samples moved
+    pub fn read_audio(&self, out: &mut [f32]) -> usize {
+        let mut audio = self.shared.audio.lock().unwrap();
+        let count = audio.len().min(out.len() / 2);
+        for ((l, r), out) in audio.drain(..count).zip(out.chunks_exact_mut(2)) {
+            out.copy_from_slice(&[l, r]);
+        }
+        count
+    }
+
+    /// Add a Game Genie or GameShark code, or rename it if it exists
+    pub fn add_cheat(&self, code: &str, name: &str) -> Result<(), CheatError> {
+        if !self.is_running() {
+            return Err(CheatError::NotRunning);
+        }
+        self.edit_cheats(|cheats| cheats.add(code, name))?;
+        log::info!("Add cheat {}", code);
+        Ok(())
+    }
 
-        log::info!("Running {}", rom_path);
-        self.is_running.store(true, Ordering::Release);
+    pub fn remove_cheat(&self, code: &str) -> bool {
+        self.is_running() && self.edit_cheats(|cheats| cheats.remove(code))
+    }
+
+    pub fn enable_cheat(&self, code: &str, enabled: bool) -> bool {
+        self.is_running() && self.edit_cheats(|cheats| cheats.set_enabled(code, enabled))
+    }
+
+    /// All cheats in the cheat file format, one cheat each line
+    pub fn list_cheats(&self) -> String {
+        self.shared.cheats.lock().unwrap().to_string()
+    }
+
+    /// Why the last run crashed, with the cpu state when it happened
+    pub fn crash_report(&self) -> Option<String> {
+        self.shared.crash_report.lock().unwrap().clone()
+    }
+
+    /// Post events of this emulator to the Dart port [port]
+    pub fn set_event_port(&self, port: i64) {
+        self.shared.events.set_port(port);
+    }
+
+    /// Pause before executing the instruction at [pc], the host gets [Event::Breakpoint]
+    pub fn add_breakpoint(&self, pc: u16) {
+        self.shared.breakpoints.lock().unwrap().insert(pc);
+        self.shared
+            .breakpoints_changed
+            .store(true, Ordering::Release);
+    }
+
+    pub fn remove_breakpoint(&self, pc: u16) -> bool {
+        let removed = self.shared.breakpoints.lock().unwrap().remove(&pc);
+        self.shared
+            .breakpoints_changed
+            .store(true, Ordering::Release);
+        removed
+    }
+
+    /// Edit cheats, save them to the cheat file and notify the emulation thread
+    fn edit_cheats<T>(&self, edit: impl FnOnce(&mut Cheats) -> T) -> T {
+        let mut cheats = self.shared.cheats.lock().unwrap();
+        let res = edit(&mut cheats);
+        cheats.save();
+        self.shared.cheats_changed.store(true, Ordering::Release);
+        res
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        self.exit();
+    }
+}
+
+/// Runs the game, it's owned by the emulation thread
+struct Runner {
+    shared: Arc<Shared>,
+    cmd_receiver: Receiver<Command>,
+    options: LoadOptions,
+    /// Where the running rom comes from, it's loaded again by a hard reset
+    source: RomSource,
+    /// The running rom after unpacking and patching, used by a soft reset
+    rom: Vec<u8>,
+    save_path: String,
+    /// Movie being recorded or played
+    movie: Option<MovieSession>,
+    /// Video being recorded
+    recorder: Option<Recorder>,
+    /// Whether battery backed ram is written to disk, it's disabled once a movie is played so
+    /// the playback never overwrites the player's save
+    persist: bool,
+    /// Frames since power-on
+    frame: u64,
+    /// Whether the rumble motor is on
+    rumble: bool,
+    /// Address of the instruction being executed, reported when the emulator crashes
+    pc: u16,
+}
+
+impl Runner {
+    fn run(mut self, source: RomSource, save_path: String, movie_request: Option<MovieRequest>) {
+        log::info!("Running {}", source);
+        let shared = self.shared.clone();
         // 主板，用于管理cpu和各种外设
-        let mut mbrd = MotherBoard::power_up(rom_path, save_path);
+        // 运行前的作弊码已被清空，保留启动过程中添加的作弊码
+        let mut mbrd = match self.load(source, save_path, true) {
+            Ok(mbrd) => mbrd,
+            Err(err) => {
+                log::error!("{}", err);
+                *shared.crash_report.lock().unwrap() = Some(err.to_string());
+                shared.events.post(Event::Crashed(err.to_string()));
+                shared.is_running.store(false, Ordering::Release);
+                return;
+            }
+        };
+        if let Some(request) = movie_request {
+            match MovieSession::start(request, &mut mbrd) {
+                Ok(session) => {
+                    self.persist = !session.is_playing();
+                    self.movie = Some(session);
+                }
+                Err(err) => log::error!("Start movie error: {}", err),
+            }
+        }
         // 初始化音频播放
         // initialize_audio(&mbrd);
 
-        // 屏幕显示的像素数据，初始化为纯黑的背景
-        let mut win_buf = vec![0x00; (u32::from(SCREEN_W) * u32::from(SCREEN_H)) as usize];
-        // 设置第一帧画面
-        self.window.update_buffer(&win_buf);
+        // 游戏出错（模拟器内部panic）时停止运行，保存游戏后报告给宿主，宿主进程不受影响
+        if let Err(err) = panic::catch_unwind(AssertUnwindSafe(|| self.run_loop(&mut mbrd))) {
+            self.crash(&mbrd, err);
+        }
+
+        if let Some(session) = self.movie.take() {
+            session.finish();
+        }
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish(&mbrd);
+        }
+        if panic::catch_unwind(AssertUnwindSafe(|| self.save(&mbrd))).is_err() {
+            log::error!("Save game failed");
+        }
+    }
+
+    /// Run the game until the host stops it
+    fn run_loop(&mut self, mbrd: &mut MotherBoard) {
+        let shared = self.shared.clone();
+        // 设置第一帧画面，纯黑的背景
+        shared
+            .window
+            .update_buffer(&[[0x00; SCREEN_W as usize]; SCREEN_H as usize]);
+        // 距离上次自动存档经过的帧数
+        let mut autosave_frames = 0;
+        // 距离上次处理按键变化经过的时钟周期
+        let mut input_cycles = 0;
+        // 已经应用到模拟时间的玩家按键状态，还没有经过连发和宏的处理
+        let mut host_keys: u8 = 0x00;
+        // 断点地址，从宿主设置的断点复制而来
+        let mut breakpoints = HashSet::new();
 
         loop {
-            if !self.is_running.load(Ordering::Acquire) {
+            if !shared.is_running.load(Ordering::Acquire) {
                 break;
             }
-            if self.is_pause.load(Ordering::Acquire) {
+            while shared.is_pause.load(Ordering::Acquire)
+                && shared.is_running.load(Ordering::Acquire)
+            {
+                // Host may still send commands (e.g. flush save) while paused
+                self.handle_commands(mbrd);
                 thread::park();
             }
 
             // 执行一条指令
-            mbrd.next();
+            self.pc = mbrd.rtc.cpu.reg.pc;
+            let cycles = mbrd.next();
 
-            // 在发生vblank时刷新屏幕数据
-            if mbrd.check_and_reset_gpu_updated() {
-                // 刷新要显示的数据
-                win_buf = (*mbrd.mmu).borrow().gpu.data.concat();
-                // 上屏
-                self.window.update_buffer(&win_buf);
+            // 下一条指令位于断点时暂停，恢复运行后从这条指令继续。pc没有变化时（如CPU处于HALT状态）不再
+            // 检查，否则恢复运行后会立即再次暂停
+            if shared.breakpoints_changed.load(Ordering::Acquire) {
+                shared.breakpoints_changed.store(false, Ordering::Release);
+                breakpoints = shared.breakpoints.lock().unwrap().clone();
+            }
+            let pc = mbrd.rtc.cpu.reg.pc;
+            if pc != self.pc && breakpoints.contains(&pc) {
+                log::info!("Breakpoint at {:#06x}", pc);
+                shared.is_pause.store(true, Ordering::Release);
+                shared.events.post(Event::Breakpoint(pc));
             }
 
-            if !mbrd.rtc.flip() {
-                continue;
+            // 按照时间戳，在模拟时间到达时应用按键的变化，录像时按键在vblank时逐帧处理
+            input_cycles += cycles;
+            if input_cycles >= INPUT_STEP_CYCLES {
+                input_cycles -= INPUT_STEP_CYCLES;
+                if self.movie.is_none() {
+                    if let Some(keys) = shared.keyboard.poll(mbrd.rtc.emulated_instant()) {
+                        host_keys = keys;
+                        apply_keys(mbrd, shared.keyboard.output(host_keys));
+                    }
+                }
             }
 
-            // 处理手柄事件
-            for (rk, vk) in KEY_MAPS {
-                if self.keyboard.is_button_pressed(rk) {
-                    mbrd.mmu.borrow_mut().joypad.keydown(vk);
-                } else {
-                    mbrd.mmu.borrow_mut().joypad.keyup(vk);
+            // 在发生vblank时刷新屏幕数据
+            if mbrd.check_and_reset_gpu_updated() {
+                // 将要显示的数据直接缩放到窗口的后台缓冲区，然后上屏
+                shared.window.update_buffer(&mbrd.mmu.borrow().gpu.data);
+                self.record_frame(mbrd);
+                self.frame += 1;
+                shared.events.post(Event::FrameReady(self.frame));
+                self.post_cartridge_events(mbrd);
+
+                shared.keyboard.next_frame();
+                let keys = match self.movie {
+                    Some(_) => self.next_frame_keys(),
+                    None => shared.keyboard.output(host_keys),
+                };
+                apply_keys(mbrd, keys);
+                self.handle_commands(mbrd);
+                // 播放或录制影片时使用影片开始时的作弊码，结束后再应用宿主的修改
+                if self.movie.is_none() && shared.cheats_changed.swap(false, Ordering::AcqRel) {
+                    mbrd.mmu.borrow_mut().cheats = shared.cheats.lock().unwrap().clone();
+                }
+                if shared.sync_changed.swap(false, Ordering::AcqRel) {
+                    mbrd.set_sync(*shared.sync_mode.lock().unwrap());
+                }
+                autosave_frames += 1;
+                if autosave_frames >= AUTOSAVE_FRAMES {
+                    autosave_frames = 0;
+                    if mbrd.mmu.borrow().cartridge.is_dirty() {
+                        self.save(mbrd);
+                    }
                 }
             }
         }
+    }
 
-        let cartridge = &mbrd.mmu.borrow().cartridge;
+    /// Enter the error state after the core panicked, the report is kept for the host and posted
+    /// as [Event::Crashed]
+    fn crash(&mut self, mbrd: &MotherBoard, err: Box<dyn Any + Send>) {
+        let reg = &mbrd.rtc.cpu.reg;
+        // 读取出错的指令时可能再次panic，或者mmu仍被借用
+        let opcode = panic::catch_unwind(AssertUnwindSafe(|| {
+            mbrd.mmu.try_borrow().ok().map(|mmu| mmu.get(self.pc))
+        }))
+        .ok()
+        .flatten();
+        let report = format!(
+            "{}\nPC={:04X} opcode={} AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X}",
+            panic_message(err.as_ref()),
+            self.pc,
+            opcode.map_or(String::from("??"), |v| format!("{:02X}", v)),
+            reg.get_af(),
+            reg.get_bc(),
+            reg.get_de(),
+            reg.get_hl(),
+            reg.sp,
+        );
+        log::error!("Emulator crashed: {}", report);
+        *self.shared.crash_report.lock().unwrap() = Some(report.clone());
+        self.shared.events.post(Event::Crashed(report));
+        self.shared.is_running.store(false, Ordering::Release);
+    }
+
+    /// Load the rom from [source] and power up a motherboard running it, cheats in memory are
+    /// kept if [keep_cheats]
+    fn load(
+        &mut self,
+        source: RomSource,
+        save_path: String,
+        keep_cheats: bool,
+    ) -> Result<MotherBoard, LoadError> {
+        log::info!("Load rom from {}", source);
+        let rom = loader::load_rom(source.clone(), &self.options)?;
+        // 卡带头部无效或不支持的卡带类型会panic
+        let options = &self.options;
+        let mbrd = panic::catch_unwind(|| MotherBoard::power_up(rom.clone(), &save_path, options))
+            .map_err(|err| LoadError::Cartridge(panic_message(err.as_ref())))?;
+        self.rom = rom;
+        self.source = source;
+        self.save_path = save_path;
+        Ok(self.setup(mbrd, keep_cheats))
+    }
+
+    /// Power up a new motherboard with the loaded rom, it has been checked by [load]
+    fn power_up(&mut self) -> MotherBoard {
+        let mbrd = MotherBoard::power_up(self.rom.clone(), &self.save_path, &self.options);
+        self.setup(mbrd, true)
+    }
+
+    /// Connect a new motherboard to the camera, cheats and save file of the running game. Cheats
+    /// are loaded from the cheat file, and the ones in memory are added to them if [keep_cheats],
+    /// so cheats aren't lost when the game has no cheat file
+    fn setup(&mut self, mut mbrd: MotherBoard, keep_cheats: bool) -> MotherBoard {
+        mbrd.mmu
+            .borrow_mut()
+            .cartridge
+            .attach_camera(self.shared.camera.clone());
+        let mut apu = APU::power_up(AUDIO_SAMPLE_RATE);
+        apu.attach_mixer(self.shared.mixer.clone());
+        // Samples of the previous game are dropped
+        self.shared.audio.lock().unwrap().clear();
+        apu.buffer = self.shared.audio.clone();
+        mbrd.mmu.borrow_mut().apu = Some(apu);
+        let mut cheats = Cheats::load(cheat_path(&self.save_path));
+        if keep_cheats && cheats.merge(&self.shared.cheats.lock().unwrap()) {
+            cheats.save();
+        }
+        mbrd.mmu.borrow_mut().cheats = cheats.clone();
+        *self.shared.cheats.lock().unwrap() = cheats;
+        self.shared.cheats_changed.store(false, Ordering::Release);
+        self.shared.sync_changed.store(false, Ordering::Release);
+        mbrd.set_sync(*self.shared.sync_mode.lock().unwrap());
+        *self.shared.rom_title.lock().unwrap() = header::title(&self.rom);
+        self.persist = true;
+        self.frame = 0;
+        if self.rumble {
+            self.rumble = false;
+            self.shared.events.post(Event::Rumble(false));
+        }
+        mbrd
+    }
+
+    /// Write battery backed ram to disk unless a movie is played
+    fn save(&self, mbrd: &MotherBoard) -> bool {
+        if !self.persist {
+            return false;
+        }
+        let cartridge = &mut mbrd.mmu.borrow_mut().cartridge;
         log::info!("Save game {}", cartridge.title());
+        let dirty = cartridge.is_dirty();
         // 保存游戏数据
-        cartridge.save();
+        let saved = cartridge.save();
+        if saved && dirty {
+            self.shared.events.post(Event::GameSaved);
+        }
+        saved
     }
 
-    pub fn is_running(&self) -> bool {
-        self.is_running.load(Ordering::Acquire)
+    /// Post bytes sent through the link cable and changes of the rumble motor
+    fn post_cartridge_events(&mut self, mbrd: &MotherBoard) {
+        let mut mmu = mbrd.mmu.borrow_mut();
+        for v in mmu.serial.sent.drain(..) {
+            self.shared.events.post(Event::SerialByte(v));
+        }
+        let rumble = mmu.cartridge.rumble();
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            self.shared.events.post(Event::Rumble(rumble));
+        }
     }
 
-    pub fn pause(&mut self) {
-        self.is_pause.store(true, Ordering::Release);
-        log::info!("Pause emulator");
+    /// Append the frame to the video being recorded, recording stops and the host is told if
+    /// writing fails. What is already recorded is saved
+    fn record_frame(&mut self, mbrd: &MotherBoard) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.next_frame(mbrd) {
+                log::error!("Record frame error: {}", err);
+                if let Some(recorder) = self.recorder.take() {
+                    recorder.finish(mbrd);
+                }
+                self.shared
+                    .events
+                    .post(Event::RecordingStopped(err.to_string()));
+            }
+        }
     }
 
-    pub fn resume(&mut self, thread: &Thread) {
-        self.is_pause.store(false, Ordering::Release);
-        thread.unpark();
-        log::info!("Resume emulator");
+    /// Save the running game and replace [mbrd] with a new one, the movie and the recording are
+    /// stopped since they can't continue after a restart. [source] is None for a soft reset which
+    /// reuses the loaded rom, cheats in memory are kept unless it's another game. The running
+    /// game is kept if the new rom can't be loaded
+    fn restart(
+        &mut self,
+        mbrd: &mut MotherBoard,
+        source: Option<(RomSource, String)>,
+        same_game: bool,
+    ) -> bool {
+        self.save(mbrd);
+        if let Some(session) = self.movie.take() {
+            session.finish();
+        }
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish(mbrd);
+        }
+        let new_mbrd = match source {
+            Some((source, save_path)) => self.load(source, save_path, same_game),
+            None => Ok(self.power_up()),
+        };
+        match new_mbrd {
+            Ok(new_mbrd) => {
+                *mbrd = new_mbrd;
+                true
+            }
+            Err(err) => {
+                log::error!("{}", err);
+                false
+            }
+        }
     }
 
-    pub fn exit(&mut self) {
-        self.is_running.store(false, Ordering::Release);
-        log::info!("Exit emulator");
+    /// Latch the joypad state of a new frame, it comes from the movie when playing one
+    fn next_frame_keys(&mut self) -> u8 {
+        let keyboard = &self.shared.keyboard;
+        // Queued changes are not used by movies, drop them
+        keyboard.poll(Instant::now());
+        let pressed = keyboard.output(keyboard.pressed_key());
+        let session = match &mut self.movie {
+            Some(session) => session,
+            None => return pressed,
+        };
+        match session.next_frame(pressed) {
+            Some(keys) => keys,
+            None => {
+                // Playback reaches the end, give the control back to the player
+                self.stop_movie();
+                pressed
+            }
+        }
     }
 
-    pub fn press_button(&mut self, btn: GbBtn) {
-        self.keyboard.press_button(btn);
-        log::info!("Press {} button", btn);
+    /// Stop the movie in the middle of a run and go back to the host's cheats, return whether
+    /// the movie was saved
+    fn stop_movie(&mut self) -> bool {
+        let saved = self.movie.take().map(MovieSession::finish);
+        self.shared.cheats_changed.store(true, Ordering::Release);
+        saved.unwrap_or(false)
     }
 
-    pub fn release_button(&mut self, btn: GbBtn) {
-        self.keyboard.release_button(btn);
-        log::info!("Release {} button", btn);
+    /// Handle all pending commands sent by the host
+    fn handle_commands(&mut self, mbrd: &mut MotherBoard) {
+        while let Ok(cmd) = self.cmd_receiver.try_recv() {
+            match cmd {
+                Command::FlushSave(reply) => {
+                    let _ = reply.send(self.save(mbrd));
+                }
+                Command::ImportSave(sav, reply) => {
+                    let imported = mbrd.mmu.borrow_mut().cartridge.import_sav(&sav);
+                    let _ = reply.send(imported);
+                }
+                Command::ExportSave(reply) => {
+                    let _ = reply.send(mbrd.mmu.borrow().cartridge.export_sav());
+                }
+                Command::StopMovie(reply) => {
+                    let _ = reply.send(self.stop_movie());
+                }
+                Command::StartRecording(path, reply) => {
+                    if let Some(recorder) = self.recorder.take() {
+                        recorder.finish(mbrd);
+                    }
+                    match Recorder::start(path, mbrd) {
+                        Ok(recorder) => self.recorder = Some(recorder),
+                        Err(err) => log::error!("Start recording error: {}", err),
+                    }
+                    let _ = reply.send(self.recorder.is_some());
+                }
+                Command::StopRecording(reply) => {
+                    let saved = self.recorder.take().map(|recorder| recorder.finish(mbrd));
+                    let _ = reply.send(saved.unwrap_or(false));
+                }
+                Command::Reset(hard, reply) => {
+                    log::info!("{} reset", if hard { "Hard" } else { "Soft" });
+                    let source = hard.then(|| (self.source.clone(), self.save_path.clone()));
+                    let _ = reply.send(self.restart(mbrd, source, true));
+                }
+                Command::LoadRom(source, save_path, reply) => {
+                    let _ = reply.send(self.restart(mbrd, Some((source, save_path)), false));
+                }
+            }
+        }
+    }
+}
+
+/// Message of a panic, [err] is the payload caught by [panic::catch_unwind]
+fn panic_message(err: &(dyn Any + Send)) -> String {
+    match err.downcast_ref::<&str>() {
+        Some(msg) => msg.to_string(),
+        None => match err.downcast_ref::<String>() {
+            Some(msg) => msg.clone(),
+            None => String::from("Unknown error"),
+        },
     }
+}
+
+/// 处理手柄事件，将按键状态同步到手柄控制器
+fn apply_keys(mbrd: &MotherBoard, keys: u8) {
+    let mut mmu = mbrd.mmu.borrow_mut();
+    for (rk, vk) in KEY_MAPS {
+        if keys & rk as u8 != 0 {
+            mmu.joypad.keydown(vk);
+        } else {
+            mmu.joypad.keyup(vk);
+        }
+    }
+}
 
-    pub fn get_window_buffer(&mut self) -> &[u32] {
-        &self.window.get_buffer()
+/// Cheats are saved next to the save directory, e.g. saves/pokemon.cht for saves/pokemon
+fn cheat_path(save_path: &str) -> PathBuf {
+    let path = Path::new(save_path);
+    match path.file_name() {
+        Some(name) => path.with_file_name(format!("{}.cht", name.to_string_lossy())),
+        None => PathBuf::new(),
     }
 }
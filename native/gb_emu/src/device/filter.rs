@@ -0,0 +1,554 @@
+//! Pixel art upscaling filters, they enlarge a frame by a small integer factor before it's scaled
+//! to the window size. Pixels are 0xAABBGGRR like in [crate::core::gpu::GPU::data]
+
+/// Which [ScaleFilter] the window uses
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub enum FilterKind {
+    None = 0,
+    Scale2x = 1,
+    Scale3x = 2,
+    Hq2x = 3,
+    Hq3x = 4,
+    Xbr2x = 5,
+}
+
+impl TryFrom<u32> for FilterKind {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, u32> {
+        match v {
+            0 => Ok(FilterKind::None),
+            1 => Ok(FilterKind::Scale2x),
+            2 => Ok(FilterKind::Scale3x),
+            3 => Ok(FilterKind::Hq2x),
+            4 => Ok(FilterKind::Hq3x),
+            5 => Ok(FilterKind::Xbr2x),
+            _ => Err(v),
+        }
+    }
+}
+
+impl FilterKind {
+    pub fn create(self) -> Option<Box<dyn ScaleFilter>> {
+        Some(match self {
+            FilterKind::None => return None,
+            FilterKind::Scale2x => Box::new(Scale2x),
+            FilterKind::Scale3x => Box::new(Scale3x),
+            FilterKind::Hq2x => Box::new(Hqx(2)),
+            FilterKind::Hq3x => Box::new(Hqx(3)),
+            FilterKind::Xbr2x => Box::new(Xbr2x),
+        })
+    }
+}
+
+/// Enlarges a frame by [factor] times
+pub trait ScaleFilter: Send + Sync {
+    fn factor(&self) -> usize;
+
+    /// Scale [src] of [w]x[h] pixels into [dst], which has (w * factor)x(h * factor) pixels
+    fn apply(&self, src: &[u32], w: usize, h: usize, dst: &mut [u32]);
+}
+
+/// Get the NxN neighbourhood of pixel ([x], [y]), pixels outside the frame are clamped to the edge
+fn neighbours<const N: usize>(
+    src: &[u32],
+    w: usize,
+    h: usize,
+    x: usize,
+    y: usize,
+) -> [[u32; N]; N] {
+    let r = N / 2;
+    std::array::from_fn(|j| {
+        let sy = (y + j).saturating_sub(r).min(h - 1);
+        std::array::from_fn(|i| src[sy * w + (x + i).saturating_sub(r).min(w - 1)])
+    })
+}
+
+/// Rotate a neighbourhood clockwise by 90 degrees
+fn rotate<const N: usize>(m: [[u32; N]; N]) -> [[u32; N]; N] {
+    std::array::from_fn(|j| std::array::from_fn(|i| m[N - 1 - i][j]))
+}
+
+/// Write the [factor]x[factor] block of source pixel ([x], [y]), [block] is row by row
+fn write_block(dst: &mut [u32], w: usize, factor: usize, x: usize, y: usize, block: &[u32]) {
+    let stride = w * factor;
+    for (j, row) in block.chunks(factor).enumerate() {
+        let start = (y * factor + j) * stride + x * factor;
+        dst[start..start + factor].copy_from_slice(row);
+    }
+}
+
+/// Weighted average of pixels channel by channel
+fn blend(pixels: &[(u32, u32)]) -> u32 {
+    let total: u32 = pixels.iter().map(|(_, w)| w).sum();
+    (0..4).fold(0, |v, c| {
+        let sum: u32 = pixels.iter().map(|(p, w)| (p >> (c * 8) & 0xff) * w).sum();
+        v | (sum / total) << (c * 8)
+    })
+}
+
+/// Convert a pixel to YUV, the color space [Hqx] and [Xbr2x] compare pixels in
+fn yuv(p: u32) -> [i32; 3] {
+    let [r, g, b] = [p & 0xff, p >> 8 & 0xff, p >> 16 & 0xff].map(|v| v as i32);
+    [
+        (299 * r + 587 * g + 114 * b) / 1000,
+        (-169 * r - 331 * g + 500 * b) / 1000,
+        (500 * r - 419 * g - 81 * b) / 1000,
+    ]
+}
+
+/// Whether two pixels look different, using the thresholds of hqx
+fn differ(a: u32, b: u32) -> bool {
+    let (a, b) = (yuv(a), yuv(b));
+    (a[0] - b[0]).abs() > 48 || (a[1] - b[1]).abs() > 7 || (a[2] - b[2]).abs() > 6
+}
+
+/// Weighted YUV distance of two pixels, used by xBR
+fn distance(a: u32, b: u32) -> u32 {
+    let (a, b) = (yuv(a), yuv(b));
+    (48 * (a[0] - b[0]).abs() + 7 * (a[1] - b[1]).abs() + 6 * (a[2] - b[2]).abs()) as u32
+}
+
+/// Scale2x (EPX), a corner takes the color of its two neighbours when they are the same
+pub struct Scale2x;
+
+impl ScaleFilter for Scale2x {
+    fn factor(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, src: &[u32], w: usize, h: usize, dst: &mut [u32]) {
+        for y in 0..h {
+            for x in 0..w {
+                let [[_, b, _], [d, e, f], [_, h2, _]] = neighbours::<3>(src, w, h, x, y);
+                let block = if b != h2 && d != f {
+                    [
+                        if d == b { d } else { e },
+                        if b == f { f } else { e },
+                        if d == h2 { d } else { e },
+                        if h2 == f { f } else { e },
+                    ]
+                } else {
+                    [e; 4]
+                };
+                write_block(dst, w, 2, x, y, &block);
+            }
+        }
+    }
+}
+
+/// Scale3x, the 3x version of [Scale2x]
+pub struct Scale3x;
+
+impl ScaleFilter for Scale3x {
+    fn factor(&self) -> usize {
+        3
+    }
+
+    fn apply(&self, src: &[u32], w: usize, h: usize, dst: &mut [u32]) {
+        for y in 0..h {
+            for x in 0..w {
+                let [[a, b, c], [d, e, f], [g, h2, i]] = neighbours::<3>(src, w, h, x, y);
+                let block = if b != h2 && d != f {
+                    let pick = |cond: bool, p: u32| if cond { p } else { e };
+                    [
+                        pick(d == b, d),
+                        pick((d == b && e != c) || (b == f && e != a), b),
+                        pick(b == f, f),
+                        pick((d == b && e != g) || (d == h2 && e != a), d),
+                        e,
+                        pick((b == f && e != i) || (h2 == f && e != c), f),
+                        pick(d == h2, d),
+                        pick((d == h2 && e != i) || (h2 == f && e != g), h2),
+                        pick(h2 == f, f),
+                    ]
+                } else {
+                    [e; 9]
+                };
+                write_block(dst, w, 3, x, y, &block);
+            }
+        }
+    }
+}
+
+/// A blend of hqx at the top left corner of w5, named after the interpolation macros of hq2x. The
+/// neighbourhood of w5 is
+///
+/// ```text
+/// w1 w2 w3
+/// w4 w5 w6
+/// w7 w8 w9
+/// ```
+#[derive(Clone, Copy)]
+enum Mix {
+    M0,
+    M10,
+    M11,
+    M12,
+    M20,
+    M21,
+    M22,
+    M60,
+    M61,
+    M70,
+    M90,
+    M100,
+}
+
+impl Mix {
+    /// The hq2x pixel in neighbourhood [n]
+    fn hq2x(self, n: &[[u32; 3]; 3]) -> u32 {
+        let [w1, w2, w4, w5] = [n[0][0], n[0][1], n[1][0], n[1][1]];
+        match self {
+            Mix::M0 => w5,
+            Mix::M10 => blend(&[(w5, 3), (w1, 1)]),
+            Mix::M11 => blend(&[(w5, 3), (w4, 1)]),
+            Mix::M12 => blend(&[(w5, 3), (w2, 1)]),
+            Mix::M20 => blend(&[(w5, 2), (w4, 1), (w2, 1)]),
+            Mix::M21 => blend(&[(w5, 2), (w1, 1), (w2, 1)]),
+            Mix::M22 => blend(&[(w5, 2), (w1, 1), (w4, 1)]),
+            Mix::M60 => blend(&[(w5, 5), (w2, 2), (w4, 1)]),
+            Mix::M61 => blend(&[(w5, 5), (w4, 2), (w2, 1)]),
+            Mix::M70 => blend(&[(w5, 6), (w4, 1), (w2, 1)]),
+            Mix::M90 => blend(&[(w5, 2), (w4, 3), (w2, 3)]),
+            Mix::M100 => blend(&[(w5, 14), (w4, 1), (w2, 1)]),
+        }
+    }
+}
+
+/// What hqx does at the top left corner of w5, the other corners are looked up with the
+/// neighbourhood rotated so they are the top left one. The first [Mix] is used when no edge
+/// crosses the corner, the second when one does
+#[derive(Clone, Copy)]
+enum Corner {
+    /// No edge can cross the corner
+    Fixed(Mix),
+    /// An edge crosses the corner when w4 and w2 look alike
+    Edge(Mix, Mix),
+    /// An edge across the top right corner, when w2 and w6 look alike, goes on through this one
+    Next(Mix, Mix),
+    /// An edge across the bottom left corner, when w8 and w4 look alike, goes on through this one
+    Prev(Mix, Mix),
+}
+
+impl Corner {
+    /// Whether an edge crosses the corner in neighbourhood [n]
+    fn edge(self, n: &[[u32; 3]; 3]) -> bool {
+        match self {
+            Corner::Fixed(_) => false,
+            Corner::Edge(..) => !differ(n[1][0], n[0][1]),
+            Corner::Next(..) => !differ(n[0][1], n[1][2]),
+            Corner::Prev(..) => !differ(n[2][1], n[1][0]),
+        }
+    }
+
+    /// The blends used without and with an edge
+    fn mixes(self) -> (Mix, Mix) {
+        match self {
+            Corner::Fixed(mix) => (mix, mix),
+            Corner::Edge(plain, edge) | Corner::Next(plain, edge) | Corner::Prev(plain, edge) => {
+                (plain, edge)
+            }
+        }
+    }
+
+    /// The hq2x pixel in neighbourhood [n], [edge] is the result of [Corner::edge]
+    fn hq2x(self, n: &[[u32; 3]; 3], edge: bool) -> u32 {
+        let (plain, with_edge) = self.mixes();
+        if edge { with_edge } else { plain }.hq2x(n)
+    }
+
+    /// The hq3x pixel in neighbourhood [n], hq3x finds edges like hq2x but blends them its own way
+    fn hq3x(self, n: &[[u32; 3]; 3], edge: bool) -> u32 {
+        let [w1, w2, w4, w5] = [n[0][0], n[0][1], n[1][0], n[1][1]];
+        let (plain, with_edge) = self.mixes();
+        if edge {
+            return match with_edge {
+                Mix::M20 => blend(&[(w5, 2), (w4, 7), (w2, 7)]),
+                Mix::M90 => blend(&[(w4, 1), (w2, 1)]),
+                _ => blend(&[(w5, 2), (w4, 1), (w2, 1)]),
+            };
+        }
+        match plain {
+            Mix::M0 => w5,
+            Mix::M11 => blend(&[(w5, 3), (w4, 1)]),
+            Mix::M12 => blend(&[(w5, 3), (w2, 1)]),
+            Mix::M20 => blend(&[(w5, 2), (w4, 1), (w2, 1)]),
+            _ => blend(&[(w5, 3), (w1, 1)]),
+        }
+    }
+}
+
+// 表中的缩写：B 不会有边缘，E 可能有边缘，S 斜边经过这个角，数字是 hq2x 的插值宏
+const B10: Corner = Corner::Fixed(Mix::M10);
+const B11: Corner = Corner::Fixed(Mix::M11);
+const B12: Corner = Corner::Fixed(Mix::M12);
+const B20: Corner = Corner::Fixed(Mix::M20);
+const B21: Corner = Corner::Fixed(Mix::M21);
+const B22: Corner = Corner::Fixed(Mix::M22);
+const E0: Corner = Corner::Edge(Mix::M0, Mix::M20);
+const E10: Corner = Corner::Edge(Mix::M10, Mix::M20);
+const E70: Corner = Corner::Edge(Mix::M10, Mix::M70);
+const E100: Corner = Corner::Edge(Mix::M0, Mix::M100);
+const S0: Corner = Corner::Edge(Mix::M0, Mix::M90);
+const S10: Corner = Corner::Edge(Mix::M10, Mix::M90);
+const SN: Corner = Corner::Next(Mix::M11, Mix::M60);
+const SP: Corner = Corner::Prev(Mix::M12, Mix::M61);
+
+/// The top left corner of each of the 256 patterns of hqx, see [pattern]. It's the case table of
+/// hq2x, whose other corners are the top left corner of the rotated pattern
+const HQ_CORNERS: [Corner; 256] = [
+//  0     1     2     3     4     5     6     7
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // 00
+    B21,  B12,  E10,  E0,   B21,  B12,  S10,  S0,   // 08
+    B20,  B20,  B22,  SN,   B20,  B20,  B22,  SN,   // 10
+    B21,  B12,  E0,   E0,   B21,  B12,  B10,  E0,   // 18
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // 20
+    B21,  B12,  S10,  S0,   B21,  B12,  E70,  E100, // 28
+    B20,  B20,  B22,  SN,   B20,  B20,  B22,  SN,   // 30
+    B21,  B12,  E70,  E0,   B21,  B12,  B10,  E100, // 38
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // 40
+    B21,  SP,   E0,   E0,   B21,  SP,   E70,  E0,   // 48
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // 50
+    B21,  B12,  E70,  E0,   B21,  B12,  E70,  E0,   // 58
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // 60
+    B21,  SP,   B10,  E0,   B21,  SP,   B10,  E100, // 68
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  SN,   // 70
+    B21,  B12,  E70,  E0,   B21,  SP,   B10,  E100, // 78
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // 80
+    B21,  B12,  E10,  E0,   B21,  B12,  S10,  S0,   // 88
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // 90
+    B21,  B12,  E70,  E0,   B21,  B12,  E70,  E0,   // 98
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // a0
+    B21,  B12,  S10,  S0,   B21,  B12,  E70,  E100, // a8
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // b0
+    B21,  B12,  E70,  S0,   B21,  B12,  B10,  E100, // b8
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // c0
+    B21,  B12,  E70,  E0,   B21,  B12,  E70,  S0,   // c8
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // d0
+    B21,  B12,  E70,  E0,   B21,  B12,  B10,  E0,   // d8
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // e0
+    B21,  B12,  E70,  E0,   B21,  B12,  B10,  E100, // e8
+    B20,  B20,  B22,  B11,  B20,  B20,  B22,  B11,  // f0
+    B21,  B12,  B10,  E0,   B21,  B12,  B10,  E100, // f8
+];
+
+/// The hqx pattern of neighbourhood [n], bits 0 to 7 are set when w1, w2, w3, w4, w6, w7, w8 and
+/// w9 differ from w5
+fn pattern(n: &[[u32; 3]; 3]) -> usize {
+    let [[w1, w2, w3], [w4, w5, w6], [w7, w8, w9]] = *n;
+    [w1, w2, w3, w4, w6, w7, w8, w9]
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| differ(w5, **p))
+        .fold(0, |v, (i, _)| v | 1 << i)
+}
+
+/// The hq3x pixel between the top left and top right corners of w5 in neighbourhood [n], [left]
+/// and [right] are those corners and whether an edge crosses them
+fn hq3x_side(n: &[[u32; 3]; 3], left: (Corner, bool), right: (Corner, bool)) -> u32 {
+    use Corner::{Edge, Next, Prev};
+    use Mix::{M100, M20, M90};
+    let [w2, w5] = [n[0][1], n[1][1]];
+    let ((l, l_edge), (r, r_edge)) = (left, right);
+    // 斜边经过两个角时中间的像素偏向 w2，否则跟随被边缘切过的角，两个角都被切过时不混合
+    let (mix, w5_weight, w2_weight) = match (l, r) {
+        (Edge(_, M90), Prev(..)) => (l_edge, 1, 3),
+        (Next(..), Edge(_, M90)) => (r_edge, 1, 3),
+        (Edge(_, M90), _) => (l_edge, 3, 1),
+        (_, Edge(_, M90)) => (r_edge, 3, 1),
+        (Edge(_, M100), Edge(_, M100)) | (Edge(_, M20), Edge(_, M20)) => (false, 1, 0),
+        (Edge(_, M100), _) => (l_edge, 7, 1),
+        (_, Edge(_, M100)) => (r_edge, 7, 1),
+        (Edge(_, M20), _) => (l_edge, 7, 1),
+        (_, Edge(_, M20)) => (r_edge, 7, 1),
+        _ => (!differ(w5, w2), 3, 1),
+    };
+    if mix {
+        blend(&[(w5, w5_weight), (w2, w2_weight)])
+    } else {
+        w5
+    }
+}
+
+/// hq2x and hq3x. Each pixel is compared in YUV with its 8 neighbours, the pattern of those that
+/// differ is looked up in [HQ_CORNERS] to find the edges and how the corners blend
+pub struct Hqx(usize);
+
+impl ScaleFilter for Hqx {
+    fn factor(&self) -> usize {
+        self.0
+    }
+
+    fn apply(&self, src: &[u32], w: usize, h: usize, dst: &mut [u32]) {
+        for y in 0..h {
+            for x in 0..w {
+                // 旋转邻域让每个角都成为左上角，依次为左上、右上、右下、左下
+                let m0 = neighbours::<3>(src, w, h, x, y);
+                let m1 = rotate(m0);
+                let m2 = rotate(m1);
+                let m3 = rotate(m2);
+                let ns = [m0, m3, m2, m1];
+                let corners = ns.map(|n| {
+                    let corner = HQ_CORNERS[pattern(&n)];
+                    (corner, corner.edge(&n))
+                });
+                let corner = |k: usize| {
+                    let (corner, edge) = corners[k];
+                    if self.0 == 2 {
+                        corner.hq2x(&ns[k], edge)
+                    } else {
+                        corner.hq3x(&ns[k], edge)
+                    }
+                };
+                if self.0 == 2 {
+                    let block = [corner(0), corner(1), corner(3), corner(2)];
+                    write_block(dst, w, 2, x, y, &block);
+                } else {
+                    let side = |k: usize| hq3x_side(&ns[k], corners[k], corners[(k + 1) % 4]);
+                    let block = [
+                        corner(0),
+                        side(0),
+                        corner(1),
+                        side(3),
+                        m0[1][1],
+                        side(1),
+                        corner(3),
+                        side(2),
+                        corner(2),
+                    ];
+                    write_block(dst, w, 3, x, y, &block);
+                }
+            }
+        }
+    }
+}
+
+/// xBR level 1 at 2x. An edge is found by comparing the weighted color distances along the two
+/// diagonals around each corner, the corner is then blended with the closer neighbour
+pub struct Xbr2x;
+
+impl Xbr2x {
+    /// Bottom right corner of the block, [m] is the 5x5 neighbourhood rotated so the corner is
+    /// there
+    fn corner(m: &[[u32; 5]; 5]) -> u32 {
+        let (b, c, d, e, f) = (m[1][2], m[1][3], m[2][1], m[2][2], m[2][3]);
+        let (g, h2, i) = (m[3][1], m[3][2], m[3][3]);
+        let (f4, i4, h5, i5) = (m[2][4], m[3][4], m[4][2], m[4][3]);
+        let edge_e = distance(e, c)
+            + distance(e, g)
+            + distance(i, f4)
+            + distance(i, h5)
+            + 4 * distance(h2, f);
+        let edge_i = distance(h2, d)
+            + distance(h2, i5)
+            + distance(f, i4)
+            + distance(f, b)
+            + 4 * distance(e, i);
+        if edge_e < edge_i && e != f && e != h2 {
+            let p = if distance(e, f) <= distance(e, h2) {
+                f
+            } else {
+                h2
+            };
+            blend(&[(e, 1), (p, 1)])
+        } else {
+            e
+        }
+    }
+}
+
+impl ScaleFilter for Xbr2x {
+    fn factor(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, src: &[u32], w: usize, h: usize, dst: &mut [u32]) {
+        for y in 0..h {
+            for x in 0..w {
+                let m0 = neighbours::<5>(src, w, h, x, y);
+                let m1 = rotate(m0);
+                let m2 = rotate(m1);
+                let m3 = rotate(m2);
+                let block = [m2, m1, m3, m0].map(|m| Self::corner(&m));
+                write_block(dst, w, 2, x, y, &block);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate() {
+        let m = [[1, 2, 3], [4, 5, 6], [7, 8, 9]];
+        assert_eq!(rotate(m), [[7, 4, 1], [8, 5, 2], [9, 6, 3]]);
+    }
+
+    #[test]
+    fn test_scale2x() {
+        // 对角线的台阶被补成斜线
+        let (k, o) = (0xff000000, 0xffffffff);
+        let src = [k, o, o, k];
+        let mut dst = [0; 16];
+        Scale2x.apply(&src, 2, 2, &mut dst);
+        assert_eq!(&dst[0..4], &[k, k, o, o]);
+        assert_eq!(&dst[4..8], &[k, o, k, o]);
+
+        // 没有边缘时每个像素只是放大
+        let mut dst = [0; 36];
+        for filter in [Box::new(Hqx(3)) as Box<dyn ScaleFilter>, Box::new(Scale3x)] {
+            filter.apply(&[o; 4], 2, 2, &mut dst);
+            assert!(dst.iter().all(|p| *p == o));
+        }
+    }
+
+    #[test]
+    fn test_hq2x() {
+        // 孤立的像素只稍微混合周围的颜色
+        let (k, o) = (0xff000000, 0xffffffff);
+        let mut dst = [0; 36];
+        Hqx(2).apply(&[o, o, o, o, k, o, o, o, o], 3, 3, &mut dst);
+        assert_eq!(&dst[14..16], &[0xff1f1f1f; 2]);
+        assert_eq!(&dst[20..22], &[0xff1f1f1f; 2]);
+
+        // 对角线沿斜边混合
+        Hqx(2).apply(&[k, o, o, o, k, o, o, o, k], 3, 3, &mut dst);
+        assert_eq!(&dst[12..18], &[0xffbfbfbf, 0xff3f3f3f, k, 0xff7f7f7f, o, o]);
+        assert_eq!(&dst[18..24], &[o, o, 0xff7f7f7f, k, 0xff3f3f3f, 0xffbfbfbf]);
+    }
+
+    #[test]
+    fn test_hq3x() {
+        // 孤立的像素被磨圆
+        let (k, o, g) = (0xff000000, 0xffffffff, 0xff7f7f7f);
+        let mut dst = [0; 81];
+        Hqx(3).apply(&[o, o, o, o, k, o, o, o, o], 3, 3, &mut dst);
+        assert_eq!(&dst[30..33], &[g, k, g]);
+        assert_eq!(&dst[39..42], &[k, k, k]);
+        assert_eq!(&dst[48..51], &[g, k, g]);
+
+        // 方块的外角被磨圆，其余的角不变
+        Hqx(3).apply(&[o, o, o, o, k, k, o, k, k], 3, 3, &mut dst);
+        assert_eq!(&dst[30..36], &[g, 0xff1f1f1f, k, k, k, k]);
+        assert_eq!(&dst[39..45], &[0xff1f1f1f, k, k, k, k, k]);
+    }
+
+    #[test]
+    fn test_xbr2x() {
+        // 对角线的两侧各混合一半
+        let (k, o, g) = (0xff000000, 0xffffffff, 0xff7f7f7f);
+        let mut dst = [0; 36];
+        Xbr2x.apply(&[k, o, o, o, k, o, o, o, k], 3, 3, &mut dst);
+        assert_eq!(&dst[6..12], &[k, k, g, o, o, o]);
+        assert_eq!(&dst[12..18], &[o, g, k, g, o, o]);
+
+        // 方块的外角被磨圆，其余的角不变
+        Xbr2x.apply(&[o, o, o, o, k, k, o, k, k], 3, 3, &mut dst);
+        assert_eq!(&dst[12..18], &[o, o, g, k, k, k]);
+        assert_eq!(&dst[18..24], &[o, o, k, k, k, k]);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::device::keyboard::GbBtn;
+
+/// Where an input comes from, each source has its own key codes and mapping
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub enum InputSource {
+    Touch = 0,
+    Gamepad = 1,
+    Keyboard = 2,
+}
+
+impl TryFrom<u32> for InputSource {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, u32> {
+        match v {
+            0 => Ok(InputSource::Touch),
+            1 => Ok(InputSource::Gamepad),
+            2 => Ok(InputSource::Keyboard),
+            _ => Err(v),
+        }
+    }
+}
+
+const SOURCE_COUNT: usize = 3;
+
+/// How to handle opposite directions (Left+Right, Up+Down) held at the same time, which is
+/// impossible on real hardware
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub enum OppositePolicy {
+    /// Pass both directions to the game
+    Allow = 0,
+    /// Only the direction pressed last is held
+    LastWins = 1,
+    /// Neither direction is held
+    Neutral = 2,
+}
+
+impl TryFrom<u32> for OppositePolicy {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, u32> {
+        match v {
+            0 => Ok(OppositePolicy::Allow),
+            1 => Ok(OppositePolicy::LastWins),
+            2 => Ok(OppositePolicy::Neutral),
+            _ => Err(v),
+        }
+    }
+}
+
+const HORIZONTAL: u8 = GbBtn::LEFT as u8 | GbBtn::RIGHT as u8;
+const VERTICAL: u8 = GbBtn::UP as u8 | GbBtn::DOWN as u8;
+
+/// Maps key codes of several input sources to Game Boy buttons and merges them.
+///
+/// A touch key code without a mapping is treated as a [GbBtn] value, so the touch overlay can
+/// send [GbBtn] directly. Unmapped codes of other sources press nothing
+pub struct InputMap {
+    /// Buttons of each key code, a key can be mapped to several buttons
+    maps: [HashMap<u32, u8>; SOURCE_COUNT],
+    /// Held key codes of each source
+    held: [HashSet<u32>; SOURCE_COUNT],
+    policy: OppositePolicy,
+    /// Direction pressed last on each axis, used by [OppositePolicy::LastWins]
+    last_horizontal: u8,
+    last_vertical: u8,
+}
+
+impl InputMap {
+    pub fn create() -> Self {
+        Self {
+            maps: Default::default(),
+            held: Default::default(),
+            policy: OppositePolicy::Allow,
+            last_horizontal: 0,
+            last_vertical: 0,
+        }
+    }
+
+    /// Map [code] of [source] to [buttons], a combination of [GbBtn] bits. 0 removes the mapping
+    pub fn map(&mut self, source: InputSource, code: u32, buttons: u8) {
+        let map = &mut self.maps[source as usize];
+        if buttons == 0 {
+            map.remove(&code);
+        } else {
+            map.insert(code, buttons);
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: OppositePolicy) {
+        self.policy = policy;
+    }
+
+    pub fn key_down(&mut self, source: InputSource, code: u32) {
+        self.held[source as usize].insert(code);
+        let buttons = self.buttons(source, code);
+        if buttons & HORIZONTAL != 0 {
+            self.last_horizontal = buttons & HORIZONTAL;
+        }
+        if buttons & VERTICAL != 0 {
+            self.last_vertical = buttons & VERTICAL;
+        }
+    }
+
+    pub fn key_up(&mut self, source: InputSource, code: u32) {
+        self.held[source as usize].remove(&code);
+    }
+
+    fn buttons(&self, source: InputSource, code: u32) -> u8 {
+        match self.maps[source as usize].get(&code) {
+            Some(buttons) => *buttons,
+            None if source == InputSource::Touch => u8::try_from(code).unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// Held buttons of all sources, with the opposite direction policy applied
+    pub fn pressed(&self) -> u8 {
+        let sources = [
+            InputSource::Touch,
+            InputSource::Gamepad,
+            InputSource::Keyboard,
+        ];
+        let mut pressed = 0;
+        for source in sources {
+            for code in &self.held[source as usize] {
+                pressed |= self.buttons(source, *code);
+            }
+        }
+        for (axis, last) in [
+            (HORIZONTAL, self.last_horizontal),
+            (VERTICAL, self.last_vertical),
+        ] {
+            if pressed & axis != axis {
+                continue;
+            }
+            match self.policy {
+                OppositePolicy::Allow => {}
+                OppositePolicy::LastWins => pressed = pressed & !axis | last,
+                OppositePolicy::Neutral => pressed &= !axis,
+            }
+        }
+        pressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opposite_policy() {
+        let mut input = InputMap::create();
+        input.map(InputSource::Gamepad, 21, GbBtn::RIGHT as u8);
+        input.key_down(InputSource::Touch, GbBtn::LEFT as u32);
+        input.key_down(InputSource::Gamepad, 21);
+        assert_eq!(input.pressed(), HORIZONTAL);
+
+        input.set_policy(OppositePolicy::LastWins);
+        assert_eq!(input.pressed(), GbBtn::RIGHT as u8);
+        input.key_up(InputSource::Gamepad, 21);
+        assert_eq!(input.pressed(), GbBtn::LEFT as u8);
+
+        input.key_down(InputSource::Touch, GbBtn::RIGHT as u32);
+        input.key_down(InputSource::Touch, GbBtn::A as u32);
+        input.set_policy(OppositePolicy::Neutral);
+        assert_eq!(input.pressed(), GbBtn::A as u8);
+    }
+
+    #[test]
+    fn test_unmapped_code() {
+        let mut input = InputMap::create();
+        // KEYCODE_BUTTON_A of Android is 0x60, it isn't B+Start
+        input.key_down(InputSource::Gamepad, 96);
+        input.key_down(InputSource::Keyboard, GbBtn::A as u32);
+        assert_eq!(input.pressed(), 0);
+        input.key_down(InputSource::Touch, GbBtn::START as u32);
+        assert_eq!(input.pressed(), GbBtn::START as u8);
+        input.map(InputSource::Gamepad, 96, GbBtn::A as u8);
+        assert_eq!(input.pressed(), GbBtn::START as u8 | GbBtn::A as u8);
+    }
+}
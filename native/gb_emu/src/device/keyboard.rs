@@ -1,5 +1,10 @@
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
 use crate::core::joypad::{JoypadKey};
+use crate::device::input::{InputMap, InputSource, OppositePolicy};
 
 /// Gameboy buttons
 #[derive(Clone, Copy)]
@@ -31,6 +36,34 @@ impl Display for GbBtn {
     }
 }
 
+impl GbBtn {
+    const ALL: [GbBtn; 8] = [
+        GbBtn::LEFT,
+        GbBtn::UP,
+        GbBtn::RIGHT,
+        GbBtn::DOWN,
+        GbBtn::A,
+        GbBtn::B,
+        GbBtn::START,
+        GbBtn::SELECT,
+    ];
+
+    /// Parse a button from its name, case insensitive
+    pub fn from_name(name: &str) -> Option<GbBtn> {
+        GbBtn::ALL
+            .into_iter()
+            .find(|btn| btn.to_string().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+impl TryFrom<u32> for GbBtn {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, u32> {
+        GbBtn::ALL.into_iter().find(|btn| *btn as u32 == v).ok_or(v)
+    }
+}
+
 /// 键盘按键和game boy按键的映射
 pub const KEY_MAPS: [(GbBtn, JoypadKey); 8] = [
     (GbBtn::RIGHT, JoypadKey::Right),
@@ -46,26 +79,273 @@ pub const KEY_MAPS: [(GbBtn, JoypadKey); 8] = [
 /// Process keyboard events
 pub struct Keyboard {
     /// Record pressed keys, each bit represent a button status, 1 is pressed, 0 is released
-    pub pressed_key: u8,
+    pressed_key: AtomicU8,
+    /// Merges keys of all input sources into [pressed_key]
+    input: Mutex<InputMap>,
+    /// Changes of [pressed_key] with the time they happen, waiting to be applied by the emulation
+    /// thread when the emulated time reaches them
+    events: Mutex<VecDeque<(Instant, u8)>>,
+    /// Turbo buttons and macros applied on top of [pressed_key]
+    layer: Mutex<TurboLayer>,
 }
 
 impl Keyboard {
     pub fn create() -> Self {
         Self {
-            pressed_key: 0x00,
+            pressed_key: AtomicU8::new(0x00),
+            input: Mutex::new(InputMap::create()),
+            events: Mutex::new(VecDeque::new()),
+            layer: Mutex::new(TurboLayer::create()),
+        }
+    }
+
+    /// Press [btn] on the touch overlay
+    pub fn press_button(&self, btn: GbBtn) {
+        self.key_down(InputSource::Touch, btn as u32);
+    }
+
+    pub fn release_button(&self, btn: GbBtn) {
+        self.key_up(InputSource::Touch, btn as u32);
+    }
+
+    /// Key [code] of [source] is pressed, see [InputMap] for how it's mapped to buttons
+    pub fn key_down(&self, source: InputSource, code: u32) {
+        self.edit_input(|input| input.key_down(source, code));
+    }
+
+    pub fn key_up(&self, source: InputSource, code: u32) {
+        self.edit_input(|input| input.key_up(source, code));
+    }
+
+    /// Map key [code] of [source] to [buttons], 0 removes the mapping
+    pub fn map_key(&self, source: InputSource, code: u32, buttons: u8) {
+        self.edit_input(|input| input.map(source, code, buttons));
+    }
+
+    pub fn set_opposite_policy(&self, policy: OppositePolicy) {
+        self.edit_input(|input| input.set_policy(policy));
+    }
+
+    /// Edit the input layer, then merge all sources into [pressed_key] and queue the change
+    fn edit_input(&self, edit: impl FnOnce(&mut InputMap)) {
+        let mut input = self.input.lock().unwrap();
+        edit(&mut input);
+        let pressed = input.pressed();
+        if self.pressed_key.swap(pressed, Ordering::AcqRel) == pressed {
+            return;
+        }
+        let mut events = self.events.lock().unwrap();
+        events.push_back((Instant::now(), pressed));
+    }
+
+    /// Buttons currently held by the player
+    pub fn pressed_key(&self) -> u8 {
+        self.pressed_key.load(Ordering::Acquire)
+    }
+
+    /// Take changes happened before [until], return the latest pressed keys among them, or None
+    /// if there is no change
+    pub fn poll(&self, until: Instant) -> Option<u8> {
+        let mut events = self.events.lock().unwrap();
+        let mut keys = None;
+        while let Some((at, pressed)) = events.front().copied() {
+            if at > until {
+                break;
+            }
+            keys = Some(pressed);
+            events.pop_front();
         }
+        keys
     }
 
-    /// Return true if [btn] pressed
-    pub fn is_button_pressed(&self, btn: GbBtn) -> bool {
-        self.pressed_key & btn as u8 != 0x00
+    /// Set the turbo rate of [btn], it's pressed and released every [frames] frames while it's
+    /// held, 0 disables turbo
+    pub fn set_turbo(&self, btn: GbBtn, frames: u8) {
+        self.layer.lock().unwrap().set_turbo(btn, frames);
     }
 
-    pub fn press_button(&mut self, btn: GbBtn) {
-        self.pressed_key |= btn as u8;
+    /// Define a macro, see [parse_macro] for the format of [steps]
+    pub fn define_macro(&self, name: &str, steps: &str) -> bool {
+        match parse_macro(steps) {
+            Some(steps) => {
+                let mut layer = self.layer.lock().unwrap();
+                layer.macros.insert(name.to_string(), steps);
+                true
+            }
+            None => false,
+        }
     }
 
-    pub fn release_button(&mut self, btn: GbBtn) {
-        self.pressed_key &= !(btn as u8);
+    /// Start playing the macro [name], return false if it doesn't exist
+    pub fn trigger_macro(&self, name: &str) -> bool {
+        self.layer.lock().unwrap().trigger(name)
     }
-}
\ No newline at end of file
+
+    /// Advance turbo buttons and macros by one frame
+    pub fn next_frame(&self) {
+        self.layer.lock().unwrap().next_frame();
+    }
+
+    /// Buttons seen by the joypad when [pressed] buttons are held
+    pub fn output(&self, pressed: u8) -> u8 {
+        self.layer.lock().unwrap().output(pressed)
+    }
+}
+
+/// Sits between [Keyboard] and the joypad, it makes held turbo buttons flip every few frames and
+/// adds the buttons of the running macro
+struct TurboLayer {
+    /// Turbo rate of each button in frames, indexed by the bit of [GbBtn], 0 means no turbo
+    turbo: [u8; 8],
+    /// Frames since the emulator started
+    frame: u32,
+    macros: HashMap<String, Vec<(u8, u32)>>,
+    /// Running macro, the remaining steps in reverse order
+    running: Vec<(u8, u32)>,
+}
+
+impl TurboLayer {
+    fn create() -> Self {
+        let mut macros = HashMap::new();
+        // 同时按下A+B+Start+Select，大部分游戏会软重启
+        macros.insert(
+            String::from("soft_reset"),
+            parse_macro("A+B+Start+Select:10").unwrap(),
+        );
+        Self {
+            turbo: [0; 8],
+            frame: 0,
+            macros,
+            running: vec![],
+        }
+    }
+
+    fn set_turbo(&mut self, btn: GbBtn, frames: u8) {
+        self.turbo[(btn as u8).trailing_zeros() as usize] = frames;
+    }
+
+    fn trigger(&mut self, name: &str) -> bool {
+        match self.macros.get(name) {
+            Some(steps) => {
+                self.running = steps.iter().rev().copied().collect();
+                log::info!("Run macro {}", name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn next_frame(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+        if let Some((_, frames)) = self.running.last_mut() {
+            *frames = frames.saturating_sub(1);
+            if *frames == 0 {
+                self.running.pop();
+            }
+        }
+    }
+
+    fn output(&self, pressed: u8) -> u8 {
+        let mut keys = pressed;
+        for (i, rate) in self.turbo.iter().enumerate() {
+            // 连发的按键按下rate帧后松开rate帧
+            if *rate != 0 && (self.frame / u32::from(*rate)) % 2 == 1 {
+                keys &= !(1 << i);
+            }
+        }
+        match self.running.last() {
+            Some((macro_keys, _)) => keys | macro_keys,
+            None => keys,
+        }
+    }
+}
+
+/// Parse macro steps separated by ',', each step is buttons joined by '+' and the frames they are
+/// held, e.g. "A+B+Start+Select:10" or "Down:2,:1,Down+A:2". Empty buttons mean a pause
+fn parse_macro(steps: &str) -> Option<Vec<(u8, u32)>> {
+    steps
+        .split(',')
+        .map(|step| {
+            let (buttons, frames) = step.split_once(':')?;
+            let frames = frames.trim().parse::<u32>().ok().filter(|f| *f > 0)?;
+            let mut keys = 0;
+            for name in buttons.split('+').filter(|b| !b.trim().is_empty()) {
+                keys |= GbBtn::from_name(name)? as u8;
+            }
+            Some((keys, frames))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_poll() {
+        let keyboard = Keyboard::create();
+        let before = Instant::now();
+        keyboard.press_button(GbBtn::A);
+        // 宿主的事件在模拟时间到达时才生效
+        assert_eq!(keyboard.poll(before), None);
+        let now = Instant::now();
+        {
+            let mut events = keyboard.events.lock().unwrap();
+            events.push_back((now + Duration::from_millis(10), 0x30));
+            events.push_back((now + Duration::from_millis(20), 0x00));
+        }
+        assert_eq!(keyboard.poll(now), Some(0x10));
+        assert_eq!(keyboard.poll(now), None);
+        // 同时到达的多个事件只保留最新的按键状态
+        assert_eq!(keyboard.poll(now + Duration::from_millis(30)), Some(0x00));
+        assert_eq!(keyboard.poll(now + Duration::from_millis(30)), None);
+    }
+
+    #[test]
+    fn test_gb_btn() {
+        assert!(matches!(GbBtn::try_from(0x40), Ok(GbBtn::START)));
+        assert_eq!(GbBtn::try_from(0x03).err(), Some(0x03));
+        assert!(matches!(GbBtn::from_name(" select "), Some(GbBtn::SELECT)));
+    }
+
+    #[test]
+    fn test_turbo() {
+        let mut layer = TurboLayer::create();
+        layer.set_turbo(GbBtn::A, 2);
+        // 按住2帧后松开2帧，没有按住的按键不受影响
+        let mut outputs = vec![];
+        for _ in 0..6 {
+            outputs.push(layer.output(0x11));
+            layer.next_frame();
+        }
+        assert_eq!(outputs, [0x11, 0x11, 0x01, 0x01, 0x11, 0x11]);
+        assert_eq!(layer.output(0x01), 0x01);
+        layer.set_turbo(GbBtn::A, 0);
+        assert_eq!(layer.output(0x11), 0x11);
+    }
+
+    #[test]
+    fn test_parse_macro() {
+        assert_eq!(
+            parse_macro("Down:2, :1,down+A:2"),
+            Some(vec![(0x08, 2), (0x00, 1), (0x18, 2)])
+        );
+        for steps in ["", "A", "A:0", "A:x", "Foo:1", "A:1,"] {
+            assert_eq!(parse_macro(steps), None, "{}", steps);
+        }
+    }
+
+    #[test]
+    fn test_soft_reset() {
+        let mut layer = TurboLayer::create();
+        assert!(!layer.trigger("missing"));
+        assert!(layer.trigger("soft_reset"));
+        // A+B+Start+Select按住10帧，同时保留玩家按下的按键
+        for _ in 0..10 {
+            assert_eq!(layer.output(0x01), 0xf1);
+            layer.next_frame();
+        }
+        assert_eq!(layer.output(0x01), 0x01);
+    }
+}
@@ -0,0 +1,104 @@
+//! Effects that imitate the LCD of the Game Boy. Ghosting and the DMG tint work on the 160x144
+//! frame, the pixel grid is drawn by [super::window::Window] after scaling
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use crate::device::window::{lerp, BLACK};
+
+/// The darkest and lightest green of the DMG screen
+const DMG_DARK: u32 = 0xff0f380f;
+const DMG_LIGHT: u32 = 0xff0fbc9b;
+
+/// Most of the previous frame that is kept, more would never let the image settle
+const MAX_PERSISTENCE: f32 = 0.9;
+
+/// Settings of the effects, they can be changed from any thread while the emulator runs
+pub struct LcdEffects {
+    /// Weight of the previous frame (0-256), 0 turns ghosting off
+    persistence: AtomicU32,
+    grid: AtomicBool,
+    dmg_tint: AtomicBool,
+    /// The last frame after effects, only used by the emulation thread
+    previous: Mutex<Vec<u32>>,
+}
+
+impl LcdEffects {
+    pub fn new(size: usize) -> Self {
+        Self {
+            persistence: AtomicU32::new(0),
+            grid: AtomicBool::new(false),
+            dmg_tint: AtomicBool::new(false),
+            previous: Mutex::new(vec![BLACK; size]),
+        }
+    }
+
+    /// Blend each frame with the previous one, [persistence] is how much of the previous frame
+    /// is kept, from 0 (off) to 0.9
+    pub fn set_ghosting(&self, persistence: f32) {
+        let persistence = persistence.clamp(0.0, MAX_PERSISTENCE);
+        self.persistence
+            .store((persistence * 256.0) as u32, Ordering::Relaxed);
+    }
+
+    /// Darken the border of each Game Boy pixel, only drawn when it's scaled by an integer factor
+    pub fn set_grid(&self, enabled: bool) {
+        self.grid.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn grid(&self) -> bool {
+        self.grid.load(Ordering::Relaxed)
+    }
+
+    /// Show the frame in the green shades of the DMG screen
+    pub fn set_dmg_tint(&self, enabled: bool) {
+        self.dmg_tint.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Apply ghosting and the tint to [frame], then pass the result to [next]. Only the emulation
+    /// thread calls it
+    pub fn apply(&self, frame: &[u32], next: impl FnOnce(&[u32])) {
+        let persistence = self.persistence.load(Ordering::Relaxed);
+        let dmg_tint = self.dmg_tint.load(Ordering::Relaxed);
+        let mut previous = self.previous.lock().unwrap();
+        if persistence == 0 && !dmg_tint {
+            // 记住这一帧，开启残影时从这里开始混合
+            previous.copy_from_slice(frame);
+            return next(frame);
+        }
+        for (last, pixel) in previous.iter_mut().zip(frame) {
+            let pixel = if dmg_tint { tint(*pixel) } else { *pixel };
+            *last = lerp(pixel, *last, persistence);
+        }
+        next(&previous)
+    }
+
+    /// Pass the last frame [apply] passed on to [next]
+    pub fn last_frame(&self, next: impl FnOnce(&[u32])) {
+        next(&self.previous.lock().unwrap())
+    }
+}
+
+/// Map the brightness of [pixel] onto the DMG greens
+fn tint(pixel: u32) -> u32 {
+    let [r, g, b] = [pixel & 0xff, pixel >> 8 & 0xff, pixel >> 16 & 0xff];
+    let luma = (r * 77 + g * 150 + b * 29) >> 8;
+    lerp(DMG_DARK, DMG_LIGHT, luma + (luma >> 7))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcd_effects() {
+        let effects = LcdEffects::new(1);
+        assert_eq!(tint(0xff000000), DMG_DARK);
+        assert_eq!(tint(0xffffffff), DMG_LIGHT);
+
+        effects.set_ghosting(0.5);
+        effects.apply(&[0xff000000], |_| {});
+        // 白色的一帧只显示一半亮度
+        effects.apply(&[0xffffffff], |frame| assert_eq!(frame, [0xff7f7f7f]));
+    }
+}
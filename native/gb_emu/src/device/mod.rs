@@ -1,3 +1,9 @@
 pub mod emulator;
 pub mod window;
+pub mod filter;
+pub mod lcd;
+pub mod pixel;
 pub mod keyboard;
+pub mod input;
+pub mod movie;
+pub mod recorder;
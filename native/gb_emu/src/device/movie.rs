@@ -0,0 +1,242 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::core::cheat::Cheats;
+use crate::core::motherboard::MotherBoard;
+
+/// Movie file signature
+const MAGIC: &[u8; 4] = b"GBMV";
+const VERSION: u8 = 2;
+
+/// Cartridge type of the Pocket Camera, its images come from the host and aren't recorded
+const POCKET_CAMERA: u8 = 0xfc;
+
+/// A recorded run: the starting state and the joypad state of every frame.
+///
+/// Movies always start from power-on. The battery backed ram, the start time of the cartridge
+/// clock and the cheats are stored, so playback doesn't depend on the local save file, the system
+/// time or the local cheat file. Cheats edited while a movie is active are applied after it
+/// stops.
+///
+/// File layout, numbers are little endian:
+/// "GBMV", version (u8), rom checksums 014D-014F (3 bytes), start unix time (u64),
+/// ram size (u32), ram in .sav format, cheats size (u32), cheats in the cheat file format,
+/// frame count (u32), one byte per frame ([Keyboard::pressed_key] bits)
+pub struct Movie {
+    checksum: [u8; 3],
+    start: u64,
+    sram: Vec<u8>,
+    cheats: String,
+    frames: Vec<u8>,
+}
+
+impl Movie {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+
+    fn decode(data: &[u8]) -> io::Result<Self> {
+        let mut pos = 0;
+        let mut next = |n: usize| take(data, &mut pos, n);
+        if next(4)? != MAGIC || next(1)?[0] != VERSION {
+            return Err(invalid());
+        }
+        let checksum = next(3)?.try_into().unwrap();
+        let start = u64::from_le_bytes(next(8)?.try_into().unwrap());
+        let len = u32::from_le_bytes(next(4)?.try_into().unwrap());
+        let sram = next(len as usize)?.to_vec();
+        let len = u32::from_le_bytes(next(4)?.try_into().unwrap());
+        let cheats = String::from_utf8(next(len as usize)?.to_vec()).map_err(|_| invalid())?;
+        let len = u32::from_le_bytes(next(4)?.try_into().unwrap());
+        let frames = next(len as usize)?.to_vec();
+        Ok(Self {
+            checksum,
+            start,
+            sram,
+            cheats,
+            frames,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let size = 28 + self.sram.len() + self.cheats.len() + self.frames.len();
+        let mut data = Vec::with_capacity(size);
+        data.extend(MAGIC);
+        data.push(VERSION);
+        data.extend(self.checksum);
+        data.extend(self.start.to_le_bytes());
+        data.extend((self.sram.len() as u32).to_le_bytes());
+        data.extend(&self.sram);
+        data.extend((self.cheats.len() as u32).to_le_bytes());
+        data.extend(self.cheats.as_bytes());
+        data.extend((self.frames.len() as u32).to_le_bytes());
+        data.extend(&self.frames);
+        data
+    }
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, "Invalid movie file")
+}
+
+/// Take [n] bytes from [data] at [pos]
+fn take<'a>(data: &'a [u8], pos: &mut usize, n: usize) -> io::Result<&'a [u8]> {
+    let v = data.get(*pos..*pos + n).ok_or_else(invalid)?;
+    *pos += n;
+    Ok(v)
+}
+
+/// Read rom checksums from the cartridge header, used to check the movie matches the rom
+fn rom_checksum(mbrd: &MotherBoard) -> [u8; 3] {
+    let mmu = mbrd.mmu.borrow();
+    [0x014d, 0x014e, 0x014f].map(|a| mmu.cartridge.get(a))
+}
+
+/// What the host asks to do with a movie on the next run
+pub enum MovieRequest {
+    Record(PathBuf),
+    Play(PathBuf),
+}
+
+enum Mode {
+    Recording(PathBuf),
+    /// Index of the next frame to play
+    Playing(usize),
+}
+
+/// A movie being recorded or played by the running emulator
+pub struct MovieSession {
+    movie: Movie,
+    mode: Mode,
+}
+
+impl MovieSession {
+    /// Start a session on a just powered up motherboard, the cartridge is switched to the emulated
+    /// clock so the run is reproducible. Pocket Camera games can't be recorded or played
+    pub fn start(request: MovieRequest, mbrd: &mut MotherBoard) -> io::Result<Self> {
+        let checksum = rom_checksum(mbrd);
+        let mut mmu = mbrd.mmu.borrow_mut();
+        if mmu.cartridge.get(0x0147) == POCKET_CAMERA {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Camera input can't be recorded in a movie",
+            ));
+        }
+        let cheats = mmu.cheats.to_string();
+        let cartridge = &mut mmu.cartridge;
+        match request {
+            MovieRequest::Record(path) => {
+                let start = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                cartridge.use_emulated_clock(start);
+                let movie = Movie {
+                    checksum,
+                    start,
+                    sram: cartridge.export_sav(),
+                    cheats,
+                    frames: vec![],
+                };
+                log::info!("Record movie to {}", path.to_string_lossy());
+                Ok(Self {
+                    movie,
+                    mode: Mode::Recording(path),
+                })
+            }
+            MovieRequest::Play(path) => {
+                let movie = Movie::load(&path)?;
+                if movie.checksum != checksum {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "The movie is recorded with another rom",
+                    ));
+                }
+                cartridge.use_emulated_clock(movie.start);
+                cartridge.restore_sav(&movie.sram);
+                mmu.cheats = Cheats::parse(&movie.cheats);
+                log::info!(
+                    "Play movie {}, {} frames",
+                    path.to_string_lossy(),
+                    movie.frames.len()
+                );
+                Ok(Self {
+                    movie,
+                    mode: Mode::Playing(0),
+                })
+            }
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, Mode::Playing(_))
+    }
+
+    /// Get the joypad state of the next frame, [pressed] is the state from the host. It returns
+    /// None when the playback reaches the end
+    pub fn next_frame(&mut self, pressed: u8) -> Option<u8> {
+        match &mut self.mode {
+            Mode::Recording(_) => {
+                self.movie.frames.push(pressed);
+                Some(pressed)
+            }
+            Mode::Playing(frame) => {
+                let keys = self.movie.frames.get(*frame).copied();
+                *frame += 1;
+                keys
+            }
+        }
+    }
+
+    /// Stop the session, the movie is saved if it's recording
+    pub fn finish(self) -> bool {
+        match self.mode {
+            Mode::Recording(path) => match self.movie.save(&path) {
+                Ok(_) => {
+                    log::info!("Movie saved, {} frames", self.movie.frames.len());
+                    true
+                }
+                Err(err) => {
+                    log::error!("Save movie to {} error: {}", path.to_string_lossy(), err);
+                    false
+                }
+            },
+            Mode::Playing(frame) => {
+                log::info!("Movie stopped at frame {}", frame);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let movie = Movie {
+            checksum: [0x01, 0x02, 0x03],
+            start: 1_600_000_000,
+            sram: vec![0xaa; 16],
+            cheats: String::from("010B3CD1\t1\tInfinite lives\n"),
+            frames: vec![0x00, 0x01, 0x81],
+        };
+        let data = movie.encode();
+        let decoded = Movie::decode(&data).unwrap();
+        assert_eq!(decoded.checksum, movie.checksum);
+        assert_eq!(decoded.start, movie.start);
+        assert_eq!(decoded.sram, movie.sram);
+        assert_eq!(decoded.cheats, movie.cheats);
+        assert_eq!(decoded.frames, movie.frames);
+        assert_eq!(Cheats::parse(&decoded.cheats).to_string(), movie.cheats);
+        // Truncated file
+        assert!(Movie::decode(&data[..data.len() - 1]).is_err());
+    }
+}
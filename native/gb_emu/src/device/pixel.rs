@@ -0,0 +1,98 @@
+//! Conversion of frames from [crate::core::gpu::GPU::data], whose pixels are 0xAABBGGRR, to the
+//! formats hosts draw
+
+/// Layout of each pixel in a buffer given by the host
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub enum PixelFormat {
+    /// 32 bits 0xAARRGGBB in native byte order
+    Argb8888 = 0,
+    /// Bytes R, G, B, A in memory order, what Flutter's `decodeImageFromPixels` takes
+    Rgba8888 = 1,
+    /// 16 bits 0bRRRRRGGGGGGBBBBB in native byte order
+    Rgb565 = 2,
+    /// One byte per pixel, the DMG color number from 0 (lightest) to 3 (darkest)
+    DmgIndex = 3,
+}
+
+impl TryFrom<u32> for PixelFormat {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, u32> {
+        match v {
+            0 => Ok(PixelFormat::Argb8888),
+            1 => Ok(PixelFormat::Rgba8888),
+            2 => Ok(PixelFormat::Rgb565),
+            3 => Ok(PixelFormat::DmgIndex),
+            _ => Err(v),
+        }
+    }
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Argb8888 | PixelFormat::Rgba8888 => 4,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::DmgIndex => 1,
+        }
+    }
+
+    /// Convert [pixels] into [out], return false if [out] is too small
+    pub fn write(self, pixels: &[u32], out: &mut [u8]) -> bool {
+        let size = self.bytes_per_pixel();
+        if out.len() < pixels.len() * size {
+            return false;
+        }
+        for (pixel, out) in pixels.iter().zip(out.chunks_exact_mut(size)) {
+            let [r, g, b, a] = pixel.to_le_bytes();
+            match self {
+                PixelFormat::Argb8888 => {
+                    let argb = u32::from_be_bytes([a, r, g, b]);
+                    out.copy_from_slice(&argb.to_ne_bytes());
+                }
+                PixelFormat::Rgba8888 => out.copy_from_slice(&[r, g, b, a]),
+                PixelFormat::Rgb565 => {
+                    let [r, g, b] = [r, g, b].map(u16::from);
+                    let rgb = (r >> 3) << 11 | (g >> 2) << 5 | b >> 3;
+                    out.copy_from_slice(&rgb.to_ne_bytes());
+                }
+                PixelFormat::DmgIndex => out[0] = dmg_index(r, g, b),
+            }
+        }
+        true
+    }
+}
+
+/// Color number of the DMG gray shade closest to the brightness of the pixel
+fn dmg_index(r: u8, g: u8, b: u8) -> u8 {
+    let [r, g, b] = [r, g, b].map(u32::from);
+    // 取GrayShades中相邻两级灰度的中点作为分界
+    match (r * 77 + g * 150 + b * 29) >> 8 {
+        0xe0.. => 0,
+        0x90.. => 1,
+        0x30.. => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_format() {
+        // 0xAABBGGRR
+        let pixels = [0xff3366ff, 0xffc0c0c0];
+        let mut out = [0; 8];
+        assert!(PixelFormat::Rgba8888.write(&pixels, &mut out));
+        assert_eq!(out, [0xff, 0x66, 0x33, 0xff, 0xc0, 0xc0, 0xc0, 0xff]);
+        assert!(PixelFormat::Argb8888.write(&pixels, &mut out));
+        assert_eq!(u32::from_ne_bytes(out[..4].try_into().unwrap()), 0xffff6633);
+        assert!(PixelFormat::Rgb565.write(&pixels, &mut out));
+        assert_eq!(u16::from_ne_bytes([out[0], out[1]]), 0xfb26);
+        assert!(PixelFormat::DmgIndex.write(&pixels, &mut out));
+        assert_eq!(out[..2], [2, 1]);
+        assert!(!PixelFormat::Argb8888.write(&pixels, &mut out[..7]));
+    }
+}
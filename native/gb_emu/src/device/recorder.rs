@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+
+use crate::core::convention::{SCREEN_H, SCREEN_W};
+use crate::core::motherboard::MotherBoard;
+use crate::tools::avi::AviWriter;
+
+/// Records gameplay to an uncompressed AVI file.
+///
+/// Frames are taken from the GPU at every VBlank by the emulation thread, so the video has
+/// exactly one frame per emulated frame no matter how many frames the host drops. Audio is the
+/// output of the APU between two VBlanks, it's silent while the game keeps the APU off. A
+/// motherboard without an APU is recorded without an audio stream, which is logged
+pub struct Recorder {
+    path: PathBuf,
+    writer: AviWriter<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn start(path: PathBuf, mbrd: &MotherBoard) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(&path)?);
+        let mut mmu = mbrd.mmu.borrow_mut();
+        let sample_rate = mmu.apu.as_ref().map(|apu| apu.sample_rate());
+        if sample_rate.is_none() {
+            log::warn!("No APU, recording {} without audio", path.to_string_lossy());
+        }
+        let writer = AviWriter::new(file, u32::from(SCREEN_W), u32::from(SCREEN_H), sample_rate)?;
+        // 从第一帧开始收集APU生成的采样
+        if let Some(apu) = &mut mmu.apu {
+            apu.capture = Some(Vec::new());
+        }
+        log::info!("Start recording to {}", path.to_string_lossy());
+        Ok(Self { path, writer })
+    }
+
+    /// Append the frame drawn at this VBlank and the sound since the last one
+    pub fn next_frame(&mut self, mbrd: &MotherBoard) -> io::Result<()> {
+        let mut mmu = mbrd.mmu.borrow_mut();
+        self.writer.write_frame(mmu.gpu.data.as_flattened())?;
+        if let Some(samples) = mmu.apu.as_mut().and_then(|apu| apu.capture.as_mut()) {
+            self.writer.write_audio(samples)?;
+            samples.clear();
+        }
+        Ok(())
+    }
+
+    /// Stop recording and complete the file, return whether it's saved
+    pub fn finish(self, mbrd: &MotherBoard) -> bool {
+        if let Ok(mut mmu) = mbrd.mmu.try_borrow_mut() {
+            if let Some(apu) = &mut mmu.apu {
+                apu.capture = None;
+            }
+        }
+        match self.writer.finish() {
+            Ok(_) => {
+                log::info!("Recording saved to {}", self.path.to_string_lossy());
+                true
+            }
+            Err(err) => {
+                log::error!(
+                    "Save recording to {} error: {}",
+                    self.path.to_string_lossy(),
+                    err
+                );
+                false
+            }
+        }
+    }
+}
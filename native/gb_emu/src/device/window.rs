@@ -1,143 +1,432 @@
-use std::collections::VecDeque;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Mutex;
 
 use crate::core::convention::{SCREEN_H, SCREEN_W};
+use crate::device::filter::{FilterKind, ScaleFilter};
+use crate::device::lcd::LcdEffects;
+use crate::device::pixel::PixelFormat;
 
 #[repr(C)]
 pub struct WindowConfig {
-    /// Scaling how many times based on original size
+    /// Scaling how many times based on original size, only used when [width] or [height] is 0
     pub scale_factor: f32,
+    /// Width of the window in pixels
+    pub width: u32,
+    /// Height of the window in pixels
+    pub height: u32,
+    /// A [ScaleMode], an invalid value falls back to [ScaleMode::Nearest]
+    pub scale_mode: u32,
+    /// A [FilterKind] applied before the frame is scaled to the window size, an invalid value
+    /// means no filter
+    pub filter: u32,
+}
+
+/// How frames are scaled to the window size
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub enum ScaleMode {
+    /// Stretch to the window with nearest-neighbor sampling, works for any real factor
+    Nearest = 0,
+    /// Scale by the largest integer factor that fits, the rest of the window is black
+    Integer = 1,
+    /// Stretch to the window with bilinear filtering
+    Bilinear = 2,
 }
 
+impl TryFrom<u32> for ScaleMode {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, u32> {
+        match v {
+            0 => Ok(ScaleMode::Nearest),
+            1 => Ok(ScaleMode::Integer),
+            2 => Ok(ScaleMode::Bilinear),
+            _ => Err(v),
+        }
+    }
+}
+
+/// Opaque black, used for letterbox bars
+pub(crate) const BLACK: u32 = 0xff000000;
+/// Weight of black in grid lines (0-256)
+const GRID_SHADE: u32 = 96;
+
 pub struct Window {
     /// window width
     pub width: u32,
     /// window height
     pub height: u32,
-    /// Scaling how many times based on original size
-    pub scale_factor: f32,
-    win_buffer: WindowBuffer,
+    pub effects: LcdEffects,
+    /// Enlarges frames before they are sampled
+    filter: Option<FilterStage>,
+    /// Width of the frame after [filter]
+    src_w: usize,
+    /// Source column of each window column
+    xs: Sampler,
+    /// Source row of each window row
+    ys: Sampler,
+    /// Whether each window column and row is on a grid line, None if the frame isn't scaled by
+    /// an integer factor
+    grid: Option<(Vec<bool>, Vec<bool>)>,
+    win_buffer: TripleBuffer,
+    /// The last frame from the GPU before effects and scaling
+    native: Mutex<Frame>,
+    /// Sequence number of the last frame written by [update_buffer]
+    frame_seq: AtomicU64,
 }
 
 impl Window {
     pub fn create(config: &WindowConfig) -> Window {
-        let width = (f32::from(SCREEN_W) * config.scale_factor).round() as u32;
-        let height = (f32::from(SCREEN_H) * config.scale_factor).round() as u32;
-        let buf_size = (width * height) as usize;
+        let (width, height) = if config.width == 0 || config.height == 0 {
+            let scale_factor = config.scale_factor.max(1.0);
+            (
+                (f32::from(SCREEN_W) * scale_factor).round() as u32,
+                (f32::from(SCREEN_H) * scale_factor).round() as u32,
+            )
+        } else {
+            (config.width, config.height)
+        };
+        let filter = FilterKind::try_from(config.filter).unwrap_or_else(|v| {
+            log::warn!("Invalid filter {}, use none", v);
+            FilterKind::None
+        });
+        let filter = filter.create().map(|filter| {
+            let size = usize::from(SCREEN_W) * usize::from(SCREEN_H) * filter.factor().pow(2);
+            FilterStage {
+                filter,
+                buffer: Mutex::new(vec![0; size]),
+            }
+        });
+        let factor = filter.as_ref().map_or(1, |stage| stage.filter.factor());
+        let (src_w, src_h) = (
+            usize::from(SCREEN_W) * factor,
+            usize::from(SCREEN_H) * factor,
+        );
+        // 每个Game Boy像素在窗口中的边长，只有整数倍时才画网格
+        let fit = (width as usize / src_w).min(height as usize / src_h).max(1);
+        let scale_mode = ScaleMode::try_from(config.scale_mode).unwrap_or_else(|v| {
+            log::warn!("Invalid scale mode {}, use nearest", v);
+            ScaleMode::Nearest
+        });
+        let cell = match scale_mode {
+            ScaleMode::Integer => Some(fit * factor),
+            ScaleMode::Nearest => (fit * src_w == width as usize && fit * src_h == height as usize)
+                .then_some(fit * factor),
+            ScaleMode::Bilinear => None,
+        };
+        let (xs, ys) = match scale_mode {
+            ScaleMode::Nearest => (
+                Sampler::nearest(src_w, width as usize, None),
+                Sampler::nearest(src_h, height as usize, None),
+            ),
+            ScaleMode::Integer => (
+                Sampler::nearest(src_w, width as usize, Some(fit)),
+                Sampler::nearest(src_h, height as usize, Some(fit)),
+            ),
+            ScaleMode::Bilinear => (
+                Sampler::bilinear(src_w, width as usize),
+                Sampler::bilinear(src_h, height as usize),
+            ),
+        };
+
+        let grid = match (&xs, &ys) {
+            (Sampler::Nearest(xs), Sampler::Nearest(ys)) if cell.is_some_and(|c| c >= 2) => {
+                Some((grid_lines(xs, factor), grid_lines(ys, factor)))
+            }
+            _ => None,
+        };
 
         Self {
             width,
             height,
-            scale_factor: config.scale_factor,
-            win_buffer: WindowBuffer::new(buf_size),
+            effects: LcdEffects::new(usize::from(SCREEN_W) * usize::from(SCREEN_H)),
+            filter,
+            src_w,
+            xs,
+            ys,
+            grid,
+            win_buffer: TripleBuffer::new((width * height) as usize),
+            native: Mutex::new(Frame {
+                seq: 0,
+                pixels: vec![BLACK; usize::from(SCREEN_W) * usize::from(SCREEN_H)],
+            }),
+            frame_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Scale a frame from the GPU into the back buffer and publish it. Only the emulation thread
+    /// calls it
+    pub fn update_buffer(&self, data: &[[u32; SCREEN_W as usize]; SCREEN_H as usize]) {
+        let seq = self.frame_seq.fetch_add(1, Ordering::Relaxed) + 1;
+        {
+            let mut native = self.native.lock().unwrap();
+            native.pixels.copy_from_slice(data.as_flattened());
+            native.seq = seq;
+        }
+        // Safety: the emulation thread is the only producer
+        let frame = unsafe { self.win_buffer.back() };
+        self.effects.apply(data.as_flattened(), |data| {
+            self.render(data, &mut frame.pixels)
+        });
+        frame.seq = seq;
+        self.win_buffer.publish();
+    }
+
+    /// Filter and scale a 160x144 frame into [out], which has the size of the window
+    fn render(&self, data: &[u32], out: &mut [u32]) {
+        match &self.filter {
+            Some(FilterStage { filter, buffer }) => {
+                let mut buffer = buffer.lock().unwrap();
+                let (w, h) = (usize::from(SCREEN_W), usize::from(SCREEN_H));
+                filter.apply(data, w, h, &mut buffer);
+                self.sample(&buffer, out);
+            }
+            None => self.sample(data, out),
         }
     }
 
-    pub fn update_buffer(&mut self, o_buffer: &[u32]) {
-        let mut row_iter = o_buffer.chunks(SCREEN_W as usize);
-        let mut buffer = self.win_buffer.get_free_buffer();
-        buffer.clear();
-        while let Some(o_row) = row_iter.next() {
-            let mut row = vec![0; self.width as usize];
-            for i in 0..row.len() {
-                row[i] = o_row[i / self.scale_factor as usize];
+    /// Scale [data], which is [src_w] pixels wide, into [out] and draw the grid on it
+    fn sample(&self, data: &[u32], out: &mut [u32]) {
+        let src_row = |y: usize| &data[y * self.src_w..(y + 1) * self.src_w];
+        let rows = out.chunks_mut(self.width as usize);
+        match (&self.xs, &self.ys) {
+            (Sampler::Nearest(xs), Sampler::Nearest(ys)) => {
+                for (row, y) in rows.zip(ys) {
+                    for (pixel, x) in row.iter_mut().zip(xs) {
+                        *pixel = match (y, x) {
+                            (Some(y), Some(x)) => data[y * self.src_w + x],
+                            _ => BLACK,
+                        };
+                    }
+                }
+            }
+            (Sampler::Bilinear(xs), Sampler::Bilinear(ys)) => {
+                for (row, (y0, y1, wy)) in rows.zip(ys) {
+                    let (row0, row1) = (src_row(*y0), src_row(*y1));
+                    for (pixel, (x0, x1, wx)) in row.iter_mut().zip(xs) {
+                        let top = lerp(row0[*x0], row0[*x1], *wx);
+                        let bottom = lerp(row1[*x0], row1[*x1], *wx);
+                        *pixel = lerp(top, bottom, *wy);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+        if let Some((xs, ys)) = self.grid.as_ref().filter(|_| self.effects.grid()) {
+            let rows = out.chunks_mut(self.width as usize);
+            for (row, on_y) in rows.zip(ys) {
+                for (pixel, on_x) in row.iter_mut().zip(xs) {
+                    if *on_x || *on_y {
+                        *pixel = lerp(*pixel, BLACK, GRID_SHADE);
+                    }
+                }
             }
-            buffer.append(&mut row.repeat(self.scale_factor as usize));
         }
-        self.win_buffer.add_render_buffer(buffer);
     }
 
-    pub fn get_buffer(&mut self) -> &[u32] {
-        self.win_buffer.get_render_buffer()
+    /// Copy the last 160x144 frame from the GPU into [out] in [format], return its sequence number
+    /// like [get_buffer], or None if [out] is too small
+    pub fn copy_native(&self, format: PixelFormat, out: &mut [u8]) -> Option<u64> {
+        let native = self.native.lock().unwrap();
+        format.write(&native.pixels, out).then_some(native.seq)
+    }
+
+    /// A copy of the last 160x144 frame from the GPU and its sequence number
+    pub fn native_frame(&self) -> (Vec<u32>, u64) {
+        let native = self.native.lock().unwrap();
+        (native.pixels.clone(), native.seq)
+    }
+
+    /// Render the last frame again as it's shown in the window, for screenshots. Unlike
+    /// [get_buffer] it can be called from any thread
+    pub fn snapshot(&self) -> Vec<u32> {
+        let mut pixels = vec![0; (self.width * self.height) as usize];
+        self.effects
+            .last_frame(|data| self.render(data, &mut pixels));
+        pixels
+    }
+
+    /// Get the latest frame and its sequence number, 0 means no frame has been drawn yet. The
+    /// pixels stay valid and unchanged until the next call.
+    ///
+    /// # Safety
+    ///
+    /// There must be a single consumer: calls must not overlap, and the pixels returned by a call
+    /// must not be used after the next one
+    pub unsafe fn get_buffer(&self) -> (&[u32], u64) {
+        let frame = self.win_buffer.front();
+        (&frame.pixels, frame.seq)
     }
 }
 
-/// A data structure that read and write window buffer, cause read and write happens in different
-/// threads, so it uses spin lock to protect enqueue and dequeue operations
-struct WindowBuffer {
-    // Store the frames that ready to be rendered
-    buffers: VecDeque<Vec<u32>>,
-    // Store the free buffers
-    caches: VecDeque<Vec<u32>>,
-    // The size of each frame
-    buf_size: usize,
-    // Spin lock
-    lock: AtomicBool,
+/// A [ScaleFilter] and the buffer it writes to, only used by the emulation thread
+struct FilterStage {
+    filter: Box<dyn ScaleFilter>,
+    buffer: Mutex<Vec<u32>>,
+}
+
+/// Which source pixels each window pixel comes from along one axis
+enum Sampler {
+    /// Index of the nearest source pixel, None inside letterbox bars
+    Nearest(Vec<Option<usize>>),
+    /// Indices of the two neighbouring source pixels and the weight of the second one (0-256)
+    Bilinear(Vec<(usize, usize, u32)>),
+}
+
+impl Sampler {
+    /// Map [dst] pixels to [src] pixels, stretched to fill [dst], or scaled by [factor] and
+    /// centered when it's given
+    fn nearest(src: usize, dst: usize, factor: Option<usize>) -> Self {
+        let map = (0..dst)
+            .map(|i| match factor {
+                // 内容居中，超出窗口的部分被裁掉
+                Some(factor) => (i + src * factor)
+                    .checked_sub((src * factor + dst) / 2)
+                    .map(|v| v / factor)
+                    .filter(|v| *v < src),
+                // 取像素中心对应的源像素
+                None => Some((2 * i + 1) * src / (2 * dst)),
+            })
+            .collect();
+        Sampler::Nearest(map)
+    }
+
+    fn bilinear(src: usize, dst: usize) -> Self {
+        let map = (0..dst)
+            .map(|i| {
+                let pos = ((i as f32 + 0.5) * src as f32 / dst as f32 - 0.5).max(0.0);
+                let i0 = (pos as usize).min(src - 1);
+                let i1 = (i0 + 1).min(src - 1);
+                (i0, i1, ((pos - i0 as f32) * 256.0) as u32)
+            })
+            .collect();
+        Sampler::Bilinear(map)
+    }
 }
 
-impl WindowBuffer {
+/// Mark the last window pixel of each Game Boy pixel in a nearest [map], [factor] is how many
+/// source pixels a Game Boy pixel has after filtering
+fn grid_lines(map: &[Option<usize>], factor: usize) -> Vec<bool> {
+    let pixel = |i: usize| map.get(i).copied().flatten().map(|v| v / factor);
+    (0..map.len())
+        .map(|i| pixel(i).is_some() && pixel(i) != pixel(i + 1))
+        .collect()
+}
+
+/// Blend pixels [a] and [b] channel by channel, [w] is the weight of [b] (0-256)
+pub(crate) fn lerp(a: u32, b: u32, w: u32) -> u32 {
+    let mix = |a: u32, b: u32| ((a * (256 - w) + b * w) >> 8) & 0x00ff00ff;
+    mix(a & 0x00ff00ff, b & 0x00ff00ff) | mix(a >> 8 & 0x00ff00ff, b >> 8 & 0x00ff00ff) << 8
+}
+
+/// Set in [TripleBuffer::middle] when the middle buffer holds a frame the consumer hasn't taken
+const NEW_FRAME: u8 = 0b100;
+const INDEX_MASK: u8 = 0b011;
+
+struct Frame {
+    seq: u64,
+    pixels: Vec<u32>,
+}
+
+/// Lock-free frame handoff between one producer (the emulation thread) and one consumer (the
+/// renderer). The producer owns the back buffer and the consumer owns the front buffer, the third
+/// buffer is exchanged with an atomic swap. Neither side waits for the other, frames are never
+/// copied, and the consumer never sees a frame being written
+struct TripleBuffer {
+    frames: [UnsafeCell<Frame>; 3],
+    /// Index of the buffer written by the producer, only used by the producer
+    back: AtomicU8,
+    /// Index of the buffer read by the consumer, only used by the consumer
+    front: AtomicU8,
+    /// Index of the spare buffer, with [NEW_FRAME] if it holds a new frame
+    middle: AtomicU8,
+}
+
+// Each buffer is only accessed by the side which owns its index
+unsafe impl Sync for TripleBuffer {}
+
+impl TripleBuffer {
     fn new(size: usize) -> Self {
+        let frame = || {
+            UnsafeCell::new(Frame {
+                seq: 0,
+                pixels: vec![0; size],
+            })
+        };
         Self {
-            buffers: VecDeque::with_capacity(2),
-            caches: VecDeque::with_capacity(2),
-            buf_size: size,
-            lock: AtomicBool::new(false),
+            frames: [frame(), frame(), frame()],
+            back: AtomicU8::new(0),
+            middle: AtomicU8::new(1),
+            front: AtomicU8::new(2),
         }
     }
 
-    /// Spin to get lock
-    fn acquire_lock(&self) {
-        while self
-            .lock
-            .compare_exchange_weak(false, true, Ordering::AcqRel, Ordering::Relaxed)
-            .is_err()
-        {}
-    }
-
-    /// Spin to release lock
-    fn release_lock(&self) {
-        while self
-            .lock
-            .compare_exchange_weak(true, false, Ordering::AcqRel, Ordering::Relaxed)
-            .is_err()
-        {}
-    }
-
-    /// Get a cached frame which can write new data to it
-    pub fn get_free_buffer(&mut self) -> Vec<u32> {
-        self.acquire_lock();
-        // Dequeue a buffer from cache
-        let buffer = match self.caches.pop_front() {
-            None => {
-                // If cache is empty, create a new frame buffer
-                Vec::with_capacity(self.buf_size)
-            }
-            Some(buf) => buf,
-        };
-        self.release_lock();
-        return buffer;
+    /// The buffer to write the next frame to.
+    ///
+    /// Safety: only one thread produces frames, and the reference is dropped before [publish]
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn back(&self) -> &mut Frame {
+        let back = self.back.load(Ordering::Relaxed);
+        &mut *self.frames[back as usize].get()
     }
 
-    /// Enqueue a frame to buffers, wait for rendering
-    pub fn add_render_buffer(&mut self, buffer: Vec<u32>) {
-        self.acquire_lock();
-        if self.buffers.len() > 1 {
-            // Move the last frame to caches (First frame is rendering now)
-            let old_buffer = self.buffers.pop_back().unwrap();
-            self.caches.push_back(old_buffer);
-        }
-        // We just ignore the old frame (moved to caches in last step) which was not rendered, and
-        // enqueue the latest frame
-        self.buffers.push_back(buffer);
-        self.release_lock();
-    }
-
-    /// Get the latest frame which ready to be rendered
-    pub fn get_render_buffer(&mut self) -> &[u32] {
-        self.acquire_lock();
-        if self.buffers.len() > 1 {
-            // Dequeue last rendered frame (the first frame) if next frame is ready
-            let old_buf = self.buffers.pop_front().unwrap();
-            // Move it to caches
-            self.caches.push_back(old_buf);
+    /// Hand the back buffer over to the consumer, the spare buffer becomes the new back buffer
+    fn publish(&self) {
+        let back = self.back.load(Ordering::Relaxed);
+        let spare = self.middle.swap(back | NEW_FRAME, Ordering::AcqRel);
+        self.back.store(spare & INDEX_MASK, Ordering::Relaxed);
+    }
+
+    /// Take the newest frame if there is one, otherwise keep the current front buffer.
+    ///
+    /// Safety: only one thread consumes frames, and the reference is dropped before the next call
+    unsafe fn front(&self) -> &Frame {
+        let mut front = self.front.load(Ordering::Relaxed);
+        if self.middle.load(Ordering::Acquire) & NEW_FRAME != 0 {
+            let newest = self.middle.swap(front, Ordering::AcqRel);
+            front = newest & INDEX_MASK;
+            self.front.store(front, Ordering::Relaxed);
         }
-        // Add an empty frame if there were no readied frames
-        if self.buffers.is_empty() {
-            self.buffers.push_back(vec![0; self.buf_size]);
+        &*self.frames[front as usize].get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampler() {
+        let Sampler::Nearest(map) = Sampler::nearest(2, 3, None) else {
+            unreachable!()
+        };
+        assert_eq!(map, [Some(0), Some(1), Some(1)]);
+        // 2x放大后居中，两侧各有1个像素的黑边
+        let Sampler::Nearest(map) = Sampler::nearest(2, 6, Some(2)) else {
+            unreachable!()
+        };
+        assert_eq!(map, [None, Some(0), Some(0), Some(1), Some(1), None]);
+        assert_eq!(
+            grid_lines(&map, 1),
+            [false, false, true, false, true, false]
+        );
+        assert_eq!(lerp(0xff000000, 0xff0000ff, 128), 0xff00007f);
+    }
+
+    #[test]
+    fn test_triple_buffer() {
+        let buffer = TripleBuffer::new(1);
+        assert_eq!(unsafe { buffer.front() }.seq, 0);
+        for seq in 1..=2 {
+            unsafe { buffer.back() }.seq = seq;
+            buffer.publish();
         }
-        // Return a fresh frame (the new first frame) for rendering
-        let buffer = self.buffers.get(0).unwrap();
-        self.release_lock();
-        return buffer;
+        // Frame 1 is dropped, the consumer only gets the newest one
+        assert_eq!(unsafe { buffer.front() }.seq, 2);
+        assert_eq!(unsafe { buffer.front() }.seq, 2);
+        unsafe { buffer.back() }.seq = 3;
+        buffer.publish();
+        assert_eq!(unsafe { buffer.front() }.seq, 3);
     }
 }
@@ -0,0 +1,293 @@
+use std::io::{self, ErrorKind, Seek, SeekFrom, Write};
+
+/// Frame rate of the Game Boy, 70224 clocks per frame at 4194304 Hz
+const FRAME_SCALE: u32 = 70224;
+const FRAME_RATE: u32 = 4194304;
+/// 16742.7us rounded up
+const MICROS_PER_FRAME: u32 = 16743;
+
+/// AVIF_HASINDEX, the file ends with an idx1 chunk
+const HAS_INDEX: u32 = 0x10;
+/// AVIIF_KEYFRAME, every uncompressed frame is a key frame
+const KEY_FRAME: u32 = 0x10;
+
+/// Players may not read an AVI 1.0 file beyond 1GB, the index included
+const MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// Writes an uncompressed AVI file: 24 bit RGB frames at the Game Boy frame rate, and optionally
+/// 16 bit stereo PCM. Counts and sizes in the headers are filled in by [finish], a file which
+/// isn't finished has no index and most players can't open it. Writing fails once the file would
+/// exceed 1GB, it can still be finished
+pub struct AviWriter<W: Write + Seek> {
+    out: W,
+    width: u32,
+    height: u32,
+    /// Position of the RIFF size
+    riff_pos: u64,
+    /// Positions of the frame count in avih, and the length of each stream in its strh
+    length_pos: [Option<u64>; 3],
+    /// Position of the size of the movi list
+    movi_pos: u64,
+    /// Chunk id, offset from the movi fourcc and size of every chunk in the movi list
+    index: Vec<(&'static [u8; 4], u32, u32)>,
+    frames: u32,
+    samples: u32,
+    /// Size the file can't exceed
+    max_size: u64,
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    /// Start a file of [width]x[height] frames, with an audio stream when [sample_rate] is given
+    pub fn new(mut out: W, width: u32, height: u32, sample_rate: Option<u32>) -> io::Result<Self> {
+        let frame_size = row_size(width) * height;
+        let start = out.stream_position()?;
+        let mut h = Vec::new();
+        let riff_pos = list(&mut h, b"RIFF", b"AVI ");
+        let hdrl = list(&mut h, b"LIST", b"hdrl");
+
+        let avih = chunk(&mut h, b"avih");
+        u32s(&mut h, &[MICROS_PER_FRAME, 0, 0, HAS_INDEX]);
+        let frames_pos = h.len();
+        let streams = if sample_rate.is_some() { 2 } else { 1 };
+        u32s(
+            &mut h,
+            &[0, 0, streams, frame_size, width, height, 0, 0, 0, 0],
+        );
+        end(&mut h, avih);
+
+        let strl = list(&mut h, b"LIST", b"strl");
+        let video_pos = stream_header(&mut h, b"vids", FRAME_SCALE, FRAME_RATE, frame_size, 0);
+        let strf = chunk(&mut h, b"strf");
+        // BITMAPINFOHEADER，高度为正数表示自下而上存储的行
+        u32s(&mut h, &[40, width, height]);
+        u16s(&mut h, &[1, 24]);
+        u32s(&mut h, &[0, frame_size, 0, 0, 0, 0]);
+        end(&mut h, strf);
+        end(&mut h, strl);
+
+        let audio_pos = sample_rate.map(|rate| {
+            let strl = list(&mut h, b"LIST", b"strl");
+            let pos = stream_header(&mut h, b"auds", 1, rate, rate * 4 / 30, 4);
+            let strf = chunk(&mut h, b"strf");
+            // WAVEFORMATEX：PCM，2声道，16位
+            u16s(&mut h, &[1, 2]);
+            u32s(&mut h, &[rate, rate * 4]);
+            u16s(&mut h, &[4, 16, 0]);
+            end(&mut h, strf);
+            end(&mut h, strl);
+            pos
+        });
+        end(&mut h, hdrl);
+
+        let movi_pos = list(&mut h, b"LIST", b"movi");
+        out.write_all(&h)?;
+        let at = |pos: usize| start + pos as u64;
+        Ok(Self {
+            out,
+            width,
+            height,
+            riff_pos: at(riff_pos),
+            length_pos: [Some(at(frames_pos)), Some(at(video_pos)), audio_pos.map(at)],
+            movi_pos: at(movi_pos),
+            index: Vec::new(),
+            frames: 0,
+            samples: 0,
+            max_size: MAX_FILE_SIZE,
+        })
+    }
+
+    /// Append a frame, [pixels] are 0xAABBGGRR like [crate::core::gpu::GPU::data]
+    pub fn write_frame(&mut self, pixels: &[u32]) -> io::Result<()> {
+        let row_size = row_size(self.width) as usize;
+        let mut data = Vec::with_capacity(row_size * self.height as usize);
+        for row in pixels.chunks(self.width as usize).rev() {
+            for [r, g, b, _] in row.iter().map(|p| p.to_le_bytes()) {
+                data.extend_from_slice(&[b, g, r]);
+            }
+            data.resize(data.len().next_multiple_of(row_size), 0);
+        }
+        self.write_chunk(b"00db", &data)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Append stereo samples in -1.0 to 1.0, it's ignored without an audio stream
+    pub fn write_audio(&mut self, samples: &[(f32, f32)]) -> io::Result<()> {
+        if self.length_pos[2].is_none() || samples.is_empty() {
+            return Ok(());
+        }
+        let pcm = |v: f32| ((v.clamp(-1.0, 1.0) * 32767.0) as i16).to_le_bytes();
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|(l, r)| [pcm(*l), pcm(*r)])
+            .flatten()
+            .collect();
+        self.write_chunk(b"01wb", &data)?;
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    /// Write the index and the sizes, and return the output
+    pub fn finish(mut self) -> io::Result<W> {
+        let movi_end = self.out.stream_position()?;
+        let mut idx = Vec::with_capacity(8 + self.index.len() * 16);
+        let idx1 = chunk(&mut idx, b"idx1");
+        for (id, offset, size) in &self.index {
+            idx.extend_from_slice(*id);
+            u32s(&mut idx, &[KEY_FRAME, *offset, *size]);
+        }
+        end(&mut idx, idx1);
+        self.out.write_all(&idx)?;
+        let file_end = self.out.stream_position()?;
+
+        let riff_size = file_end - self.riff_pos - 4;
+        let movi_size = movi_end - self.movi_pos - 4;
+        let [frames, video, audio] = self.length_pos;
+        let fields = [
+            (Some(self.riff_pos), size32(riff_size)?),
+            (Some(self.movi_pos), size32(movi_size)?),
+            (frames, self.frames),
+            (video, self.frames),
+            (audio, self.samples),
+        ];
+        for (pos, v) in fields {
+            if let Some(pos) = pos {
+                self.out.seek(SeekFrom::Start(pos))?;
+                self.out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        self.out.seek(SeekFrom::Start(file_end))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_chunk(&mut self, id: &'static [u8; 4], data: &[u8]) -> io::Result<()> {
+        let pos = self.out.stream_position()?;
+        // 写入后的文件大小，包括finish写入的索引，每个块的索引占16字节
+        let chunk_end = pos + 8 + data.len().next_multiple_of(2) as u64;
+        let index_size = 8 + (self.index.len() as u64 + 1) * 16;
+        if chunk_end + index_size - self.riff_pos + 4 > self.max_size {
+            return Err(io::Error::new(
+                ErrorKind::FileTooLarge,
+                "AVI file reached the 1GB limit",
+            ));
+        }
+        // 索引中的偏移从movi的fourcc开始计算
+        let offset = size32(pos - self.movi_pos - 4)?;
+        let size = size32(data.len() as u64)?;
+        self.out.write_all(id)?;
+        self.out.write_all(&size.to_le_bytes())?;
+        self.out.write_all(data)?;
+        if data.len() % 2 == 1 {
+            self.out.write_all(&[0])?;
+        }
+        self.index.push((id, offset, size));
+        Ok(())
+    }
+}
+
+/// Convert a size or an offset to the 32 bit field of AVI 1.0
+fn size32(v: u64) -> io::Result<u32> {
+    u32::try_from(v).map_err(|_| io::Error::new(ErrorKind::FileTooLarge, "AVI file is too large"))
+}
+
+/// Bytes of a row of 24 bit pixels, rows are padded to 4 bytes
+fn row_size(width: u32) -> u32 {
+    (width * 3).next_multiple_of(4)
+}
+
+fn u32s(h: &mut Vec<u8>, values: &[u32]) {
+    for v in values {
+        h.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+fn u16s(h: &mut Vec<u8>, values: &[u16]) {
+    for v in values {
+        h.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+/// Start a chunk, return the position of its size which is set by [end]
+fn chunk(h: &mut Vec<u8>, id: &[u8; 4]) -> usize {
+    h.extend_from_slice(id);
+    h.extend_from_slice(&[0; 4]);
+    h.len() - 4
+}
+
+/// Start a list, return the position of its size
+fn list(h: &mut Vec<u8>, id: &[u8; 4], kind: &[u8; 4]) -> usize {
+    let pos = chunk(h, id);
+    h.extend_from_slice(kind);
+    pos
+}
+
+/// Set the size of the chunk started at [pos]
+fn end(h: &mut [u8], pos: usize) {
+    let size = (h.len() - pos - 4) as u32;
+    h[pos..pos + 4].copy_from_slice(&size.to_le_bytes());
+}
+
+/// Write a strh chunk, return the position of its length
+fn stream_header(
+    h: &mut Vec<u8>,
+    kind: &[u8; 4],
+    scale: u32,
+    rate: u32,
+    buffer_size: u32,
+    sample_size: u32,
+) -> usize {
+    let strh = chunk(h, b"strh");
+    h.extend_from_slice(kind);
+    // 未压缩的数据没有编码器
+    u32s(h, &[0, 0, 0, 0, scale, rate, 0]);
+    let length_pos = h.len();
+    u32s(h, &[0, buffer_size, u32::MAX, sample_size, 0, 0]);
+    end(h, strh);
+    length_pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_avi_writer() {
+        let mut avi = AviWriter::new(Cursor::new(Vec::new()), 2, 2, Some(100)).unwrap();
+        avi.write_frame(&[0xff0000ff, 0xff00ff00, 0xffff0000, 0xffffffff])
+            .unwrap();
+        avi.write_audio(&[(1.0, -1.0)]).unwrap();
+        let data = avi.finish().unwrap().into_inner();
+        let u32_at = |pos: usize| u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+
+        assert_eq!(data[..4], *b"RIFF");
+        assert_eq!(u32_at(4) as usize, data.len() - 8);
+        // avih的总帧数
+        assert_eq!(u32_at(48), 1);
+        let movi = data.windows(4).position(|v| v == b"movi").unwrap();
+        // 第一帧从最后一行开始，BGR顺序
+        assert_eq!(data[movi + 4..movi + 8], *b"00db");
+        assert_eq!(data[movi + 12..movi + 15], [0xff, 0x00, 0x00]);
+        assert_eq!(data[movi + 28..movi + 32], *b"01wb");
+        assert_eq!(data[movi + 36..movi + 40], [0xff, 0x7f, 0x01, 0x80]);
+        assert_eq!(data[data.len() - 40..data.len() - 36], *b"idx1");
+    }
+
+    #[test]
+    fn test_avi_size_limit() {
+        let mut avi = AviWriter::new(Cursor::new(Vec::new()), 2, 2, None).unwrap();
+        let frame = [0u32; 4];
+        avi.write_frame(&frame).unwrap();
+        let size = avi.out.get_ref().len() as u64;
+        // 还能写入一帧：8字节的块头，16字节的像素，两个块的索引
+        avi.max_size = size + 24 + 8 + 2 * 16;
+        avi.write_frame(&frame).unwrap();
+        let err = avi.write_frame(&frame).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::FileTooLarge);
+
+        let data = avi.finish().unwrap().into_inner();
+        assert_eq!(data.len() as u64, size + 24 + 8 + 2 * 16);
+        assert_eq!(data[48..52], 2u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use allo_isolate::Isolate;
+
+/// Kinds of [Event], the first element of every posted message
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub enum EventKind {
+    FrameReady = 0,
+    GameSaved = 1,
+    Crashed = 2,
+    Rumble = 3,
+    SerialByte = 4,
+    Breakpoint = 5,
+    RecordingStopped = 6,
+}
+
+/// Something happened in the emulator that the host may react to
+pub enum Event {
+    /// A new frame is ready in the window buffer, with the number of frames since power-on
+    FrameReady(u64),
+    /// Battery backed ram is written to disk
+    GameSaved,
+    /// The emulator stopped because of an error, with the error message
+    Crashed(String),
+    /// The rumble motor of the cartridge is turned on or off
+    Rumble(bool),
+    /// The game sent a byte through the link cable
+    SerialByte(u8),
+    /// The emulator is paused because it reached a breakpoint, with the address
+    Breakpoint(u16),
+    /// Recording stopped because writing the file failed, e.g. it reached the size limit, with
+    /// the error message
+    RecordingStopped(String),
+}
+
+/// Posts [Event]s to a Dart port, like [super::logger::FLogger] does for log messages.
+///
+/// Each event is posted as a list `[kind, value]`, kind is [EventKind], value is an int for most
+/// events, a bool for [Event::Rumble] and a string for [Event::Crashed] and
+/// [Event::RecordingStopped]. Nothing is posted until a port is set
+#[derive(Default)]
+pub struct EventSink {
+    /// Native port of the Dart ReceivePort, 0 means not set
+    port: AtomicI64,
+}
+
+impl EventSink {
+    pub fn set_port(&self, port: i64) {
+        self.port.store(port, Ordering::Release);
+    }
+
+    /// Post [event] to the host, return false if there is no port or the port is closed
+    pub fn post(&self, event: Event) -> bool {
+        let port = self.port.load(Ordering::Acquire);
+        if port == 0 {
+            return false;
+        }
+        let isolate = Isolate::new(port);
+        match event {
+            Event::FrameReady(frame) => isolate.post((EventKind::FrameReady as i32, frame as i64)),
+            Event::GameSaved => isolate.post((EventKind::GameSaved as i32, 0i64)),
+            Event::Crashed(msg) => isolate.post((EventKind::Crashed as i32, msg)),
+            Event::Rumble(on) => isolate.post((EventKind::Rumble as i32, on)),
+            Event::SerialByte(v) => isolate.post((EventKind::SerialByte as i32, i64::from(v))),
+            Event::Breakpoint(pc) => isolate.post((EventKind::Breakpoint as i32, i64::from(pc))),
+            Event::RecordingStopped(msg) => {
+                isolate.post((EventKind::RecordingStopped as i32, msg))
+            }
+        }
+    }
+}
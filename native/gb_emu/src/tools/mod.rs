@@ -1 +1,4 @@
-pub mod logger;
\ No newline at end of file
+pub mod logger;
+pub mod event;
+pub mod avi;
+pub mod png;
@@ -0,0 +1,66 @@
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Encode an 8 bit RGBA image as PNG. [pixels] are 0xAABBGGRR like [crate::core::gpu::GPU::data],
+/// each of [texts] is written to a tEXt chunk as (keyword, text), both should be Latin-1
+pub fn encode(width: u32, height: u32, pixels: &[u32], texts: &[(&str, &str)]) -> Vec<u8> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+    let mut png = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 位深度8，颜色类型6（RGBA），默认的压缩、过滤方式，不隔行扫描
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    for (keyword, text) in texts {
+        let data = [keyword.as_bytes(), &[0], text.as_bytes()].concat();
+        write_chunk(&mut png, b"tEXt", &data);
+    }
+
+    // 每一行以过滤类型开头，0表示不过滤
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in pixels.chunks(width as usize) {
+        let bytes: Vec<u8> = row.iter().flat_map(|p| p.to_le_bytes()).collect();
+        // 写入内存不会失败
+        encoder.write_all(&[0]).unwrap();
+        encoder.write_all(&bytes).unwrap();
+    }
+    write_chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Append a chunk with its length and CRC
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32fast::hash(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let png = encode(2, 1, &[0xff0000ff, 0x80ff0000], &[("Title", "TETRIS")]);
+        assert_eq!(png[..8], SIGNATURE);
+        // IHDR的CRC
+        assert_eq!(png[29..33], [0xf4, 0x22, 0x7f, 0x8a]);
+        assert_eq!(png[37..41], *b"tEXt");
+        assert_eq!(png[41..53], *b"Title\0TETRIS");
+        assert_eq!(
+            png[png.len() - 12..],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+#include <stdbool.h>
+#include <stddef.h>
 #include <stdint.h>
 
 typedef struct Emulator Emulator_C;
@@ -14,23 +16,280 @@ typedef enum
     SELECT = 0x80,
 } GbBtn;
 
+typedef enum
+{
+    TOUCH = 0,
+    GAMEPAD = 1,
+    KEYBOARD = 2,
+} InputSource;
+
+// Kind of an event posted by set_event_port, the first element of the message
+typedef enum
+{
+    FRAME_READY = 0,
+    GAME_SAVED = 1,
+    CRASHED = 2,
+    RUMBLE = 3,
+    SERIAL_BYTE = 4,
+    BREAKPOINT = 5,
+    // Recording stopped because writing failed or the file reached 1GB, the value is the error
+    RECORDING_STOPPED = 6,
+} EventKind;
+
+// How Left+Right and Up+Down held at the same time are handled
+typedef enum
+{
+    ALLOW = 0,
+    LAST_WINS = 1,
+    NEUTRAL = 2,
+} OppositePolicy;
+
+// How frames are scaled to the window size
+typedef enum
+{
+    // Stretch to the window with nearest-neighbor sampling
+    NEAREST = 0,
+    // Largest integer factor that fits, centered with black bars
+    INTEGER = 1,
+    // Stretch to the window with bilinear filtering
+    BILINEAR = 2,
+} ScaleMode;
+
+// Pixel art upscaling applied before a frame is scaled to the window size
+typedef enum
+{
+    FILTER_NONE = 0,
+    SCALE2X = 1,
+    SCALE3X = 2,
+    // Edges are looked up in the 256 neighbourhood patterns of hqx and blended, softer than
+    // SCALE2X/SCALE3X
+    HQ2X = 3,
+    HQ3X = 4,
+    XBR2X = 5,
+} FilterKind;
+
 typedef struct
 {
+    // Only used when width or height is 0
     float scale_factor;
+    // Size of the window in pixels
+    uint32_t width;
+    uint32_t height;
+    ScaleMode scale_mode;
+    FilterKind filter;
 } WindowConfig;
 
+typedef struct
+{
+    char title[17];
+    // 0x80: supports CGB functions, 0xc0: works on CGB only
+    uint8_t cgb_flag;
+    // 0x03: supports SGB functions
+    uint8_t sgb_flag;
+    // 0x33 means new_licensee is used instead
+    uint8_t old_licensee;
+    char new_licensee[3];
+    uint8_t cart_type;
+    char cart_type_name[32];
+    // In bytes, 0 if the size code is unknown
+    uint32_t rom_size;
+    uint32_t ram_size;
+    uint8_t version;
+    uint8_t header_checksum;
+    uint16_t global_checksum;
+    bool logo_ok;
+    bool header_checksum_ok;
+    bool global_checksum_ok;
+} RomInfo;
+
+// Each emulator runs in its own thread, several emulators can run at the same time
 Emulator_C *create_emulator(WindowConfig *win_config);
 
 void run_emulator(Emulator_C *emulator, char *rom_path, char *save_path);
 
+// Run the rom stored in memory, the data can also be a .zip or .gz archive
+void run_emulator_from_bytes(Emulator_C *emulator, const uint8_t *data, size_t len, char *save_path);
+
+// Load roms with bad checksums or non-standard sizes with warnings, call it before run_emulator
+void set_lenient_loading(Emulator_C *emulator, bool lenient);
+
+// Apply an IPS/BPS/UPS patch on the next run, NULL uses the patch next to the rom with the same name
+void set_patch_path(Emulator_C *emulator, char *patch_path);
+
+// Parse the cartridge header without starting emulation, return false if the rom can't be read
+bool read_rom_info(char *rom_path, RomInfo *info);
+
+bool read_rom_info_from_bytes(const uint8_t *data, size_t len, RomInfo *info);
+
+// Get the latest frame. Frames have a single consumer: call it and get_window_frame from one
+// thread only, and stop using the pixels once either is called again
 uint32_t *get_window_buffer(Emulator_C *emulator);
 
+// Same as get_window_buffer, seq grows by one for each new frame, so repeated frames can be skipped
+uint32_t *get_window_frame(Emulator_C *emulator, uint64_t *seq);
+
+// Layout of each pixel in a buffer passed to copy_native_frame
+typedef enum
+{
+    // 32 bits 0xAARRGGBB in native byte order
+    ARGB8888 = 0,
+    // Bytes R, G, B, A in memory order
+    RGBA8888 = 1,
+    // 16 bits in native byte order
+    RGB565 = 2,
+    // One byte per pixel, DMG color number 0 (lightest) to 3 (darkest)
+    DMG_INDEX = 3,
+} PixelFormat;
+
+// Copy the native 160x144 frame into buffer of len bytes, return false if buffer is NULL or too small
+bool copy_native_frame(Emulator_C *emulator, PixelFormat format, uint8_t *buffer, size_t len);
+
+// Write the last frame to path as PNG, scaled like the window or at 160x144. With metadata the
+// game title and frame number are stored in tEXt chunks
+bool save_screenshot(Emulator_C *emulator, const char *path, bool scaled, bool metadata);
+
+// Blend each frame with the previous one like a slow LCD, persistence is 0 (off) to 0.9
+void set_ghosting(Emulator_C *emulator, float persistence);
+
+// Draw a grid between Game Boy pixels, only at integer scales
+void set_pixel_grid(Emulator_C *emulator, bool enabled);
+
+// Show frames in the green shades of the DMG screen
+void set_dmg_tint(Emulator_C *emulator, bool enabled);
+
 void press_button(Emulator_C *emulator, GbBtn btn);
 
 void release_button(Emulator_C *emulator, GbBtn btn);
 
+// Key code of source is pressed, unmapped touch codes are treated as GbBtn values, unmapped codes
+// of other sources are ignored
+void key_down(Emulator_C *emulator, InputSource source, uint32_t code);
+
+void key_up(Emulator_C *emulator, InputSource source, uint32_t code);
+
+// Map key code of source to buttons, a combination of GbBtn bits, 0 removes the mapping
+void map_key(Emulator_C *emulator, InputSource source, uint32_t code, uint8_t buttons);
+
+void set_opposite_policy(Emulator_C *emulator, OppositePolicy policy);
+
+// Press and release btn, a GbBtn value, every frames frames while it's held, 0 disables turbo.
+// Other values of btn are ignored
+void set_turbo(Emulator_C *emulator, uint32_t btn, uint8_t frames);
+
+// Steps are separated by ',', e.g. "A+B+Start+Select:10", return false if steps is invalid
+bool define_macro(Emulator_C *emulator, char *name, char *steps);
+
+// Run a macro, "soft_reset" is always available
+bool trigger_macro(Emulator_C *emulator, char *name);
+
+// Feed a 128x112 grayscale frame (0 is black, 255 is white) to the Game Boy Camera
+void set_camera_frame(Emulator_C *emulator, const uint8_t *gray);
+
 void pause_emulator(Emulator_C *emulator);
 
 void resume_emulator(Emulator_C *emulator);
 
+// Write battery backed ram to disk now, return false if it failed
+bool flush_save(Emulator_C *emulator);
+
+// Replace battery backed ram with a .sav file, e.g. exported by other emulators
+bool import_save(Emulator_C *emulator, char *sav_path);
+
+// Export battery backed ram as a .sav file
+bool export_save(Emulator_C *emulator, char *sav_path);
+
+// Add a Game Genie (ABC-DEF or ABC-DEF-GHI) or GameShark (ABCDEFGH) code to the running game,
+// return false if it's invalid, a GameShark code doesn't write ram (A000-DFFF) or no game is
+// running
+bool add_cheat(Emulator_C *emulator, char *code, char *name);
+
+bool remove_cheat(Emulator_C *emulator, char *code);
+
+bool enable_cheat(Emulator_C *emulator, char *code, bool enabled);
+
+// One cheat each line: "<code>\t<1 or 0>\t<name>", release it by free_cheat_list
+char *list_cheats(Emulator_C *emulator);
+
+void free_cheat_list(char *list);
+
+// Record the joypad state of every frame from power-on, call it before run_emulator
+void record_movie(Emulator_C *emulator, char *movie_path);
+
+// Play a movie recorded by record_movie, call it before run_emulator
+void play_movie(Emulator_C *emulator, char *movie_path);
+
+// Stop the movie, a recording movie is saved
+bool stop_movie(Emulator_C *emulator);
+
+// Sound channels of the APU
+typedef enum
+{
+    SQUARE1 = 0,
+    SQUARE2 = 1,
+    WAVE = 2,
+    NOISE = 3,
+} AudioChannel;
+
+// Volume from 0.0 to 1.0, applied on top of the game's own mixing
+void set_channel_volume(Emulator_C *emulator, AudioChannel channel, float volume);
+
+void set_channel_muted(Emulator_C *emulator, AudioChannel channel, bool muted);
+
+// Volume of the final output from 0.0 to 1.0
+void set_master_volume(Emulator_C *emulator, float volume);
+
+// How emulation keeps the speed of the real Game Boy
+typedef enum
+{
+    // Sleep to keep the frame rate, for video-only use
+    SYNC_VIDEO = 0,
+    // Wait for the audio buffer to be played, the host must keep calling read_audio
+    SYNC_AUDIO = 1,
+} SyncMode;
+
+void set_sync_mode(Emulator_C *emulator, SyncMode mode);
+
+// Keep the output of each channel, before the volumes above, for read_channel_tap
+void set_channel_taps(Emulator_C *emulator, bool enabled);
+
+// Move kept samples of channel into buffer of len floats as interleaved left and right samples,
+// return the number of stereo samples moved
+size_t read_channel_tap(Emulator_C *emulator, AudioChannel channel, float *buffer, size_t len);
+
+// Sample rate of the audio returned by read_audio
+#define AUDIO_SAMPLE_RATE 48000
+
+// Move the audio of the running game into buffer of len floats as interleaved left and right
+// samples, return the number of stereo samples moved. At most 1 second is kept, read it regularly
+size_t read_audio(Emulator_C *emulator, float *buffer, size_t len);
+
+// Record video and audio to an uncompressed AVI file, one frame per emulated frame. Recording
+// stops at 1GB and a RECORDING_STOPPED event is posted
+bool start_recording(Emulator_C *emulator, char *path);
+
+// Stop recording and save the file
+bool stop_recording(Emulator_C *emulator);
+
+// Save and restart the running game, a hard reset reads the rom and cheats from disk again
+bool reset_emulator(Emulator_C *emulator, bool hard);
+
+// Save the running game and switch to another rom, the current game keeps running on failure
+bool load_rom(Emulator_C *emulator, char *rom_path, char *save_path);
+
+// Why the last run stopped with an error, with the cpu state if it crashed. Return NULL if there
+// was no error, otherwise release it by free_crash_report
+char *get_crash_report(Emulator_C *emulator);
+
+void free_crash_report(char *report);
+
+// Post events to a Dart port as [kind, value] lists, value is an int, a bool for RUMBLE and a
+// string for CRASHED
+void set_event_port(Emulator_C *emulator, int64_t port, void *post_c_object);
+
+// Pause before executing the instruction at pc, a BREAKPOINT event is posted
+void add_breakpoint(Emulator_C *emulator, uint16_t pc);
+
+bool remove_breakpoint(Emulator_C *emulator, uint16_t pc);
+
+// Stop the emulator, wait for its thread to save the game and finish, then release it
 void exit_emulator(Emulator_C *emulator);
\ No newline at end of file